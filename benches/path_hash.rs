@@ -0,0 +1,52 @@
+#[macro_use]
+extern crate criterion;
+extern crate rio;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use criterion::{black_box, Criterion};
+use rio::{Path, PathBuf};
+
+const PATHS: &[&str] = &["tiles/0/1/2/3/4.png", "/tiles/0/1/2/3/4.png/", "a", "assets//js/overviewer.js"];
+
+// hashes each component in turn, as paths were hashed before
+fn naive_hash(p: &Path) -> u64 {
+    let mut h = DefaultHasher::new();
+    for c in p.iter() {
+        c.hash(&mut h);
+    }
+    h.finish()
+}
+
+fn hash(p: &Path) -> u64 {
+    let mut h = DefaultHasher::new();
+    p.hash(&mut h);
+    h.finish()
+}
+
+fn path_hash(c: &mut Criterion) {
+    let paths: Vec<PathBuf> = PATHS.iter().map(PathBuf::from).collect();
+    c.bench_function("hash naive", |b| b.iter(|| {
+        for p in &paths {
+            black_box(naive_hash(black_box(p)));
+        }
+    }));
+    c.bench_function("hash", |b| b.iter(|| {
+        for p in &paths {
+            black_box(hash(black_box(p)));
+        }
+    }));
+    c.bench_function("eq naive", |b| b.iter(|| {
+        for p in &paths {
+            black_box(p.iter().eq(black_box(p).iter()));
+        }
+    }));
+    c.bench_function("eq", |b| b.iter(|| {
+        for p in &paths {
+            black_box(p == black_box(p));
+        }
+    }));
+}
+
+criterion_group!(benches, path_hash);
+criterion_main!(benches);
@@ -0,0 +1,199 @@
+use std::{io, path};
+use std::future::{self, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio;
+use path::{Path, PathBuf};
+use fs::{Error, Result, FileType};
+
+/// A boxed future, as returned by `AsyncFSRead`.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Asynchronous operations for readable file systems.
+///
+/// This mirrors `FSRead`, but every operation returns a future so it
+/// can be used without blocking an async runtime. Paths are the same
+/// `Path` type used by the blocking API.
+pub trait AsyncFSRead {
+    type ReadFile: tokio::io::AsyncRead + Unpin + Send;
+    fn open<'s, P: AsRef<Path>>(&'s self, path: P) -> BoxFuture<'s, Result<Self::ReadFile>>;
+
+    fn file_type<'s, P: AsRef<Path>>(&'s self, path: P) -> BoxFuture<'s, Result<FileType>>;
+
+    /// List the paths of all entries in a directory.
+    fn read_dir<'s, P: AsRef<Path>>(&'s self, path: P) -> BoxFuture<'s, Result<Vec<PathBuf>>>;
+}
+
+/// A native, local filesystem driven by `tokio::fs`.
+///
+/// This is the async counterpart to `Native`, and passes operations
+/// through to the local file system under a prefix.
+pub struct TokioNative {
+    inner: path::PathBuf,
+}
+
+impl TokioNative {
+    pub fn new<P: AsRef<path::Path>>(path: P) -> TokioNative {
+        TokioNative { inner: path.as_ref().to_path_buf() }
+    }
+
+    fn path<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<path::PathBuf> {
+        let path = path.as_ref();
+        if !path.is_safe() {
+            let err = io::Error::new(io::ErrorKind::PermissionDenied, "Path leaves the filesystem.");
+            return Err(Error::new(op, path, err));
+        }
+        let mut p = self.inner.clone();
+        for part in path {
+            p.push(part.as_str());
+        }
+        return Ok(p);
+    }
+}
+
+// applies f to the output of a boxed future
+struct Map<T, F> {
+    fut: BoxFuture<'static, T>,
+    f: Option<F>,
+}
+
+fn map<T, U, F, G>(fut: F, f: G) -> BoxFuture<'static, U>
+    where F: Future<Output = T> + Send + 'static, G: FnOnce(T) -> U + Send + Unpin + 'static,
+          T: 'static, U: 'static
+{
+    Box::pin(Map { fut: Box::pin(fut), f: Some(f) })
+}
+
+impl<T, U, F: FnOnce(T) -> U + Unpin> Future for Map<T, F> {
+    type Output = U;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<U> {
+        match self.fut.as_mut().poll(cx) {
+            Poll::Ready(v) => {
+                let f = self.f.take().expect("future polled after completion");
+                Poll::Ready(f(v))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// opens a directory, then collects every entry
+struct ReadDir {
+    open: Option<BoxFuture<'static, io::Result<tokio::fs::ReadDir>>>,
+    dir: Option<tokio::fs::ReadDir>,
+    root: path::PathBuf,
+    path: PathBuf,
+    entries: Vec<PathBuf>,
+}
+
+impl Future for ReadDir {
+    type Output = Result<Vec<PathBuf>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<Vec<PathBuf>>> {
+        let this = &mut *self;
+        if let Some(mut open) = this.open.take() {
+            match open.as_mut().poll(cx) {
+                Poll::Ready(Ok(dir)) => this.dir = Some(dir),
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::new("read directory", &this.path, e))),
+                Poll::Pending => {
+                    this.open = Some(open);
+                    return Poll::Pending;
+                }
+            }
+        }
+        let dir = this.dir.as_mut().expect("future polled after completion");
+        loop {
+            match dir.poll_next_entry(cx) {
+                Poll::Ready(Ok(Some(entry))) => {
+                    let p = entry.path();
+                    if let Some(s) = p.strip_prefix(&this.root).ok().and_then(|p| p.to_str()) {
+                        this.entries.push(PathBuf::from(s));
+                    }
+                }
+                Poll::Ready(Ok(None)) => {
+                    return Poll::Ready(Ok(::std::mem::replace(&mut this.entries, Vec::new())));
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(Error::new("read directory", &this.path, e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncFSRead for TokioNative {
+    type ReadFile = tokio::fs::File;
+
+    fn open<'s, P: AsRef<Path>>(&'s self, path: P) -> BoxFuture<'s, Result<tokio::fs::File>> {
+        let path = path.as_ref().to_path_buf();
+        let p = match self.path("open", &path) {
+            Ok(p) => p,
+            Err(e) => return Box::pin(future::ready(Err(e))),
+        };
+        map(tokio::fs::File::open(p), move |r| r.map_err(|e| Error::new("open", path, e)))
+    }
+
+    fn file_type<'s, P: AsRef<Path>>(&'s self, path: P) -> BoxFuture<'s, Result<FileType>> {
+        let path = path.as_ref().to_path_buf();
+        let p = match self.path("stat", &path) {
+            Ok(p) => p,
+            Err(e) => return Box::pin(future::ready(Err(e))),
+        };
+        map(tokio::fs::metadata(p), move |r| {
+            let ft = r.ok().and_then(|m| {
+                if m.is_file() {
+                    Some(FileType::File)
+                } else if m.is_dir() {
+                    Some(FileType::Dir)
+                } else {
+                    None
+                }
+            });
+            ft.ok_or_else(|| {
+                let err = io::Error::new(io::ErrorKind::NotFound, "File not found.");
+                Error::new("stat", path, err)
+            })
+        })
+    }
+
+    fn read_dir<'s, P: AsRef<Path>>(&'s self, path: P) -> BoxFuture<'s, Result<Vec<PathBuf>>> {
+        let p = match self.path("read directory", &path) {
+            Ok(p) => p,
+            Err(e) => return Box::pin(future::ready(Err(e))),
+        };
+        Box::pin(ReadDir {
+            open: Some(Box::pin(tokio::fs::read_dir(p))),
+            dir: None,
+            root: self.inner.clone(),
+            path: path.as_ref().to_path_buf(),
+            entries: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn tokio_readwrite() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = TokioNative::new(t.path());
+        let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        rt.block_on(tokio::fs::write(t.path().join("foo"), b"test")).unwrap();
+        let mut f = rt.block_on(n.open("/foo")).unwrap();
+        let mut v = Vec::new();
+        rt.block_on(f.read_to_end(&mut v)).unwrap();
+        assert_eq!(v, b"test");
+
+        assert!(rt.block_on(n.file_type("foo")).unwrap().is_file());
+        assert!(rt.block_on(n.file_type("")).unwrap().is_dir());
+        assert!(rt.block_on(n.file_type("missing")).is_err());
+        assert_eq!(rt.block_on(n.read_dir("")).unwrap(), vec![PathBuf::from("foo")]);
+        assert!(rt.block_on(n.open("missing")).is_err());
+        assert!(rt.block_on(n.open("../foo")).is_err());
+    }
+}
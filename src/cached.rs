@@ -0,0 +1,108 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use path::{Path, PathBuf};
+use fs::{FSRead, Result, FileType, Metadata, Permissions, QPath, Requalify};
+
+/// A filesystem wrapper that remembers file types.
+///
+/// Successful `file_type` lookups (and so `exists`, `is_file` and
+/// `is_dir`) are answered from memory after the first call for each
+/// path. File contents are never cached. If the filesystem underneath
+/// changes, call `clear_cache`.
+pub struct Cached<T> {
+    inner: T,
+    cache: RefCell<HashMap<PathBuf, FileType>>,
+}
+
+impl<T> Cached<T> {
+    pub fn new(inner: T) -> Cached<T> {
+        Cached { inner: inner, cache: RefCell::new(HashMap::new()) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Forget all remembered file types.
+    pub fn clear_cache(&self) {
+        self.cache.borrow_mut().clear();
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Cached<T> {
+    type ReadFile = T::ReadFile;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<T::ReadFile> {
+        self.inner.open(path)
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(T::ReadFile, Metadata)> {
+        self.inner.open_meta(path)
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        if let Some(ft) = self.cache.borrow().get(path.as_ref()) {
+            return Ok(*ft);
+        }
+        let ft = self.inner.file_type(&path)?;
+        self.cache.borrow_mut().insert(path.as_ref().to_path_buf(), ft);
+        Ok(ft)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, Cached<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS, Counting};
+
+    #[test]
+    fn cached_file_type() {
+        let c = Cached::new(Counting::new(MemFS::new()));
+        c.get_ref().get_ref().create("a").unwrap();
+        assert!(c.exists("a"));
+        assert!(c.exists("/a/"));
+        assert!(c.is_file("a"));
+        assert_eq!(c.get_ref().file_types(), 1);
+
+        c.clear_cache();
+        assert!(c.exists("a"));
+        assert_eq!(c.get_ref().file_types(), 2);
+
+        assert!(!c.exists("b"));
+        assert!(!c.exists("b"));
+        assert_eq!(c.get_ref().file_types(), 4);
+    }
+}
@@ -0,0 +1,234 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read};
+use std::rc::Rc;
+use path::{Path, PathBuf};
+use fs::{Error, FSRead, Result, FileType, Metadata, Permissions, QPath, Requalify};
+
+struct Entry {
+    data: Rc<[u8]>,
+    used: u64,
+}
+
+struct Contents {
+    entries: HashMap<PathBuf, Entry>,
+    size: usize,
+    clock: u64,
+}
+
+impl Contents {
+    fn get(&mut self, path: &Path) -> Option<Rc<[u8]>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(path).map(|e| {
+            e.used = clock;
+            e.data.clone()
+        })
+    }
+
+    fn insert(&mut self, path: PathBuf, data: Rc<[u8]>, budget: usize) {
+        if let Some(old) = self.entries.remove(&path) {
+            self.size -= old.data.len();
+        }
+        while self.size + data.len() > budget {
+            let oldest = match self.entries.iter().min_by_key(|&(_, e)| e.used) {
+                Some((p, _)) => p.clone(),
+                None => break,
+            };
+            let e = self.entries.remove(&oldest).unwrap();
+            self.size -= e.data.len();
+        }
+        self.clock += 1;
+        self.size += data.len();
+        self.entries.insert(path, Entry { data: data, used: self.clock });
+    }
+}
+
+/// A filesystem wrapper that remembers file contents.
+///
+/// Files no larger than `max_file` bytes are read in full the first
+/// time they are opened, and later opens are served from memory. The
+/// cache holds at most `budget` bytes, and the least recently opened
+/// files are dropped to make room. If the filesystem underneath
+/// changes, call `clear_cache`.
+pub struct ContentCache<T> {
+    inner: T,
+    budget: usize,
+    max_file: usize,
+    cache: RefCell<Contents>,
+}
+
+impl<T> ContentCache<T> {
+    pub fn new(inner: T, budget: usize, max_file: usize) -> ContentCache<T> {
+        let contents = Contents { entries: HashMap::new(), size: 0, clock: 0 };
+        ContentCache { inner: inner, budget: budget, max_file: max_file, cache: RefCell::new(contents) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// The total size of the file contents currently held.
+    pub fn cached_bytes(&self) -> usize {
+        self.cache.borrow().size
+    }
+
+    /// Forget all remembered file contents.
+    pub fn clear_cache(&self) {
+        let mut cache = self.cache.borrow_mut();
+        cache.entries.clear();
+        cache.size = 0;
+    }
+}
+
+impl<'a, T: FSRead<'a>> ContentCache<T> {
+    // read a newly opened file, and remember it if it fits
+    fn load(&self, path: &Path, mut f: T::ReadFile) -> Result<ContentFile<T::ReadFile>> {
+        // read one byte past the limit to tell whether the file fits
+        let mut head = Vec::new();
+        (&mut f).take((self.max_file as u64).saturating_add(1)).read_to_end(&mut head).map_err(|e| Error::new("open", path, e))?;
+        if head.len() > self.max_file || head.len() > self.budget {
+            return Ok(ContentFile::Uncached(Cursor::new(head).chain(f)));
+        }
+        let data: Rc<[u8]> = head.into();
+        self.cache.borrow_mut().insert(path.to_path_buf(), data.clone(), self.budget);
+        Ok(ContentFile::Cached(Cursor::new(data)))
+    }
+}
+
+/// A file opened through `ContentCache`.
+pub enum ContentFile<R> {
+    /// The contents came from, or were just added to, the cache.
+    Cached(Cursor<Rc<[u8]>>),
+    /// The file was too large to cache, and is read from the inner
+    /// filesystem.
+    Uncached(io::Chain<Cursor<Vec<u8>>, R>),
+}
+
+impl<R: Read> Read for ContentFile<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            ContentFile::Cached(ref mut r) => r.read(buf),
+            ContentFile::Uncached(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for ContentCache<T> {
+    type ReadFile = ContentFile<T::ReadFile>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<ContentFile<T::ReadFile>> {
+        let path = path.as_ref();
+        if let Some(data) = self.cache.borrow_mut().get(path) {
+            return Ok(ContentFile::Cached(Cursor::new(data)));
+        }
+        let f = self.inner.open(path)?;
+        self.load(path, f)
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(ContentFile<T::ReadFile>, Metadata)> {
+        // always read afresh, so the contents match the metadata
+        let (f, meta) = self.inner.open_meta(&path)?;
+        Ok((self.load(path.as_ref(), f)?, meta))
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.inner.file_type(path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, ContentCache<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS, Counting};
+    use std::io::{Read, Write};
+
+    fn read(c: &ContentCache<Counting<MemFS>>, path: &str) -> Vec<u8> {
+        let mut v = Vec::new();
+        c.open(path).unwrap().read_to_end(&mut v).unwrap();
+        v
+    }
+
+    #[test]
+    fn content_cache_hit() {
+        let c = ContentCache::new(Counting::new(MemFS::new()), 100, 10);
+        c.get_ref().get_ref().create("a").unwrap().write_all(b"small").unwrap();
+        assert_eq!(read(&c, "a"), b"small");
+        assert_eq!(read(&c, "/a/"), b"small");
+        assert_eq!(c.get_ref().opens(), 1);
+        assert_eq!(c.cached_bytes(), 5);
+
+        c.clear_cache();
+        assert_eq!(read(&c, "a"), b"small");
+        assert_eq!(c.get_ref().opens(), 2);
+    }
+
+    #[test]
+    fn content_cache_large() {
+        let c = ContentCache::new(Counting::new(MemFS::new()), 100, 10);
+        c.get_ref().get_ref().create("big").unwrap().write_all(&[7; 50]).unwrap();
+        assert_eq!(read(&c, "big"), vec![7; 50]);
+        assert_eq!(read(&c, "big"), vec![7; 50]);
+        assert_eq!(c.get_ref().opens(), 2);
+        assert_eq!(c.cached_bytes(), 0);
+
+        // no limit on file size, only the budget
+        let c = ContentCache::new(Counting::new(MemFS::new()), 100, usize::MAX);
+        c.get_ref().get_ref().create("a").unwrap().write_all(b"small").unwrap();
+        assert_eq!(read(&c, "a"), b"small");
+        assert_eq!(c.cached_bytes(), 5);
+    }
+
+    #[test]
+    fn content_cache_evict() {
+        let c = ContentCache::new(Counting::new(MemFS::new()), 20, 10);
+        for name in &["a", "b", "c"] {
+            c.get_ref().get_ref().create(name).unwrap().write_all(&[0; 8]).unwrap();
+        }
+        read(&c, "a");
+        read(&c, "b");
+        // touch a so b is the oldest
+        read(&c, "a");
+        read(&c, "c");
+        assert_eq!(c.get_ref().opens(), 3);
+        assert_eq!(c.cached_bytes(), 16);
+        read(&c, "a");
+        read(&c, "c");
+        assert_eq!(c.get_ref().opens(), 3);
+        read(&c, "b");
+        assert_eq!(c.get_ref().opens(), 4);
+    }
+}
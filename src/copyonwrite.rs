@@ -0,0 +1,221 @@
+use std::{io, vec};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Metadata, OpenOptions, Permissions, QPath};
+use layers::{Layer, Layers};
+
+/// A filesystem that layers a writable upper filesystem over a
+/// read-only base.
+///
+/// Reads come from the base until a path is written to. The first
+/// write copies the file up from the base into the upper filesystem,
+/// and from then on it is read and written there. New files only ever
+/// live in the upper filesystem, and the base is never modified.
+/// Removing a base file hides it, but that is only remembered for as
+/// long as this `CopyOnWrite` lives.
+pub struct CopyOnWrite<'a, B: 'a + ?Sized, U: 'a + ?Sized> {
+    layers: Layers<'a, B>,
+    upper: &'a U,
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+impl<'a, B: ?Sized, U: ?Sized> CopyOnWrite<'a, B, U> {
+    pub fn new(base: &'a B, upper: &'a U) -> CopyOnWrite<'a, B, U> {
+        CopyOnWrite { layers: Layers::new(base), upper: upper }
+    }
+
+    pub fn get_ref(&self) -> (&'a B, &'a U) {
+        (self.layers.base, self.upper)
+    }
+}
+
+/// A file opened through `CopyOnWrite`, from either layer.
+pub enum CopyOnWriteFile<R, S> {
+    Base(R),
+    Upper(S),
+}
+
+impl<R: io::Read, S: io::Read> io::Read for CopyOnWriteFile<R, S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            CopyOnWriteFile::Base(ref mut r) => r.read(buf),
+            CopyOnWriteFile::Upper(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+pub struct ReadDir<'a, B: 'a + ?Sized, U: 'a + ?Sized> {
+    iter: vec::IntoIter<PathBuf>,
+    parent: &'a CopyOnWrite<'a, B, U>,
+}
+
+impl<'a, B: ?Sized + FSRead<'a>, U: ?Sized + FSRead<'a>> Iterator for ReadDir<'a, B, U> {
+    type Item = QPath<'a, CopyOnWrite<'a, B, U>>;
+
+    fn next(&mut self) -> Option<QPath<'a, CopyOnWrite<'a, B, U>>> {
+        self.iter.next().map(|p| self.parent.qualified(p))
+    }
+}
+
+impl<'a, B: ?Sized + FSRead<'a>, U: ?Sized + FSRead<'a>> FSRead<'a> for CopyOnWrite<'a, B, U> {
+    type ReadFile = CopyOnWriteFile<B::ReadFile, U::ReadFile>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadFile> {
+        match self.layers.layer(self.upper, &path) {
+            Layer::Upper => self.upper.open(path).map(CopyOnWriteFile::Upper),
+            Layer::Base => self.layers.base.open(path).map(CopyOnWriteFile::Base),
+            Layer::Hidden => Err(error("open", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.layers.file_type(self.upper, path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self.layers.layer(self.upper, &path) {
+            Layer::Upper => self.upper.is_symlink(path),
+            Layer::Base => self.layers.base.is_symlink(path),
+            Layer::Hidden => false,
+        }
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        match self.layers.layer(self.upper, &path) {
+            Layer::Upper => self.upper.metadata(path),
+            Layer::Base => self.layers.base.metadata(path),
+            Layer::Hidden => Err(error("stat", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    type ReadDir = ReadDir<'a, B, U>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a, B, U>> {
+        let children = self.layers.children(self.upper, path)?;
+        Ok(ReadDir { iter: children.into_iter(), parent: self })
+    }
+}
+
+impl<'a, B: ?Sized + FSRead<'a>, U: ?Sized + FSRead<'a> + FSWrite<'a>> FSWrite<'a> for CopyOnWrite<'a, B, U> {
+    type WriteFile = U::WriteFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<U::WriteFile> {
+        self.layers.create(self.upper, path)
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<U::WriteFile> {
+        self.layers.copy_up(self.upper, "append to", &path)?;
+        self.upper.append(path)
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<U::WriteFile> {
+        opts.check(&path)?;
+        if opts.create_new && self.exists(&path) {
+            return Err(error("open", path, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        match self.layers.layer(self.upper, &path) {
+            Layer::Base if self.layers.base.is_file(&path) => self.layers.copy_up(self.upper, "open", &path)?,
+            _ => self.layers.prepare_parent(self.upper, "open", &path)?,
+        }
+        self.upper.open_options(path, opts)
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        self.layers.copy_up(self.upper, "truncate", &path)?;
+        self.upper.truncate(path, len)
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.layers.copy_up(self.upper, "set permissions on", &path)?;
+        self.upper.set_permissions(path, perm)
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.layers.hard_link(self.upper, existing, link)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.layers.rename(self.upper, from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.layers.remove_file(self.upper, path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.layers.remove_dir(self.upper, path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.layers.create_dir(self.upper, path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS};
+    use std::io::{Read, Write};
+
+    fn read<'a, T: FSRead<'a>>(fs: &T, path: &str) -> String {
+        let mut s = String::new();
+        fs.open(path).unwrap().read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let base = MemFS::new();
+        base.create_dir("assets").unwrap();
+        base.create("assets/style.css").unwrap().write_all(b"body {}").unwrap();
+        base.create("assets/app.js").unwrap().write_all(b"app").unwrap();
+        let upper = MemFS::new();
+        let cow = CopyOnWrite::new(&base, &upper);
+
+        // unmodified files are read from the base
+        assert_eq!(read(&cow, "assets/app.js"), "app");
+        assert!(!upper.exists("assets/app.js"));
+
+        cow.create("assets/style.css").unwrap().write_all(b"p {}").unwrap();
+        cow.append("assets/app.js").unwrap().write_all(b"!").unwrap();
+        assert_eq!(read(&cow, "assets/style.css"), "p {}");
+        assert_eq!(read(&cow, "assets/app.js"), "app!");
+        assert_eq!(read(&upper, "assets/app.js"), "app!");
+        assert_eq!(read(&base, "assets/style.css"), "body {}");
+        assert_eq!(read(&base, "assets/app.js"), "app");
+
+        // files that only exist in the upper layer
+        cow.create_dir("new").unwrap();
+        cow.create("new/a").unwrap().write_all(b"a").unwrap();
+        assert_eq!(read(&cow, "new/a"), "a");
+        assert!(!base.exists("new"));
+
+        cow.remove_file("assets/style.css").unwrap();
+        assert!(!cow.exists("assets/style.css"));
+        assert!(base.is_file("assets/style.css"));
+        let listing: Vec<PathBuf> = cow.read_dir("").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(listing, vec!["assets", "new"]);
+        let assets: Vec<PathBuf> = cow.read_dir("assets").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(assets, vec!["assets/app.js"]);
+    }
+
+    #[test]
+    fn copy_on_write_rename_partial() {
+        let base = MemFS::new();
+        base.create_dir("d").unwrap();
+        base.create("d/a").unwrap().write_all(b"a").unwrap();
+        base.create("d/b").unwrap().write_all(b"b").unwrap();
+        let upper = MemFS::new();
+        let cow = CopyOnWrite::new(&base, &upper);
+
+        // d is mirrored in the upper layer to hold c, but a and b aren't
+        cow.create("d/c").unwrap().write_all(b"c").unwrap();
+        cow.rename("d", "x").unwrap();
+        let x: Vec<PathBuf> = cow.read_dir("x").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(x, vec!["x/a", "x/b", "x/c"]);
+        assert_eq!(read(&cow, "x/a"), "a");
+        assert!(!cow.exists("d"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Result, FileType, Metadata, OpenOptions, Permissions, QPath, Requalify};
+
+/// A filesystem wrapper that counts the operations passed through it.
+///
+/// This is mostly useful in tests, to check how often another wrapper
+/// actually touches the filesystem underneath it.
+pub struct Counting<T> {
+    inner: T,
+    opens: AtomicUsize,
+    file_types: AtomicUsize,
+    read_dirs: AtomicUsize,
+    creates: AtomicUsize,
+}
+
+impl<T> Counting<T> {
+    pub fn new(inner: T) -> Counting<T> {
+        Counting {
+            inner: inner,
+            opens: AtomicUsize::new(0),
+            file_types: AtomicUsize::new(0),
+            read_dirs: AtomicUsize::new(0),
+            creates: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// The number of calls to `open`.
+    pub fn opens(&self) -> usize {
+        self.opens.load(Ordering::SeqCst)
+    }
+
+    /// The number of calls to `file_type`, including those made by
+    /// `exists`, `is_file` and `is_dir`.
+    pub fn file_types(&self) -> usize {
+        self.file_types.load(Ordering::SeqCst)
+    }
+
+    /// The number of calls to `read_dir`.
+    pub fn read_dirs(&self) -> usize {
+        self.read_dirs.load(Ordering::SeqCst)
+    }
+
+    /// The number of calls to `create`, `append` and `open_options`.
+    pub fn creates(&self) -> usize {
+        self.creates.load(Ordering::SeqCst)
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Counting<T> {
+    type ReadFile = T::ReadFile;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<T::ReadFile> {
+        self.opens.fetch_add(1, Ordering::SeqCst);
+        self.inner.open(path)
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(T::ReadFile, Metadata)> {
+        self.opens.fetch_add(1, Ordering::SeqCst);
+        self.inner.open_meta(path)
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.file_types.fetch_add(1, Ordering::SeqCst);
+        self.inner.file_type(path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, Counting<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.read_dirs.fetch_add(1, Ordering::SeqCst);
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.read_dirs.fetch_add(1, Ordering::SeqCst);
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        self.read_dirs.fetch_add(1, Ordering::SeqCst);
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+impl<'a, T: FSWrite<'a>> FSWrite<'a> for Counting<T> {
+    type WriteFile = T::WriteFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.creates.fetch_add(1, Ordering::SeqCst);
+        self.inner.create(path)
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.creates.fetch_add(1, Ordering::SeqCst);
+        self.inner.append(path)
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<T::WriteFile> {
+        self.creates.fetch_add(1, Ordering::SeqCst);
+        self.inner.open_options(path, opts)
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        self.inner.truncate(path, len)
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.inner.set_permissions(path, perm)
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.inner.hard_link(existing, link)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.create_dir(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS};
+
+    #[test]
+    fn counting() {
+        let c = Counting::new(MemFS::new());
+        c.create("a").unwrap();
+        assert!(c.open("a").is_ok());
+        assert!(c.exists("a"));
+        assert!(!c.is_file("b"));
+        assert_eq!(c.read_dir("").unwrap().count(), 1);
+        assert_eq!((c.opens(), c.file_types(), c.read_dirs(), c.creates()), (1, 2, 1, 1));
+    }
+}
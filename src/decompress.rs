@@ -0,0 +1,231 @@
+use std::io;
+use flate2::read::GzDecoder;
+use path::{Path, PathBuf};
+use fs::{FSRead, Error, Result, FileType, Metadata, Permissions, QPath};
+
+/// A filesystem wrapper that transparently decompresses gzip files.
+///
+/// A file stored as "name.gz" in the inner filesystem appears here as
+/// "name", and reads of it are decompressed on the fly. Files without
+/// a ".gz" extension are passed through untouched, so compressed and
+/// uncompressed trees can be read interchangeably. If both "name" and
+/// "name.gz" exist, the compressed one wins and only it is listed.
+pub struct Decompress<T> {
+    inner: T,
+}
+
+impl<T> Decompress<T> {
+    pub fn new(inner: T) -> Decompress<T> {
+        Decompress { inner: inner }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T: FSRead<'a>> Decompress<T> {
+    // the compressed file standing in for a path, if there is one
+    fn compressed<P: AsRef<Path>>(&self, path: P) -> Option<PathBuf> {
+        gz_path(path).filter(|gz| self.inner.is_file(gz))
+    }
+
+    // the name an inner entry is listed under, or None if it is
+    // hidden by a compressed file of the same name
+    fn listed(&self, entry: QPath<'a, T>) -> Option<PathBuf> {
+        let path = entry.path();
+        if let Some((name, parent)) = path.split_last() {
+            if name.len() > 3 && name.ends_with(".gz") && self.inner.is_file(path) {
+                return Some(parent.join(&name[..name.len() - 3]));
+            }
+        }
+        match self.compressed(path) {
+            Some(_) => None,
+            None => Some(path.to_path_buf()),
+        }
+    }
+}
+
+// the compressed name for a path, if it has a file name at all
+fn gz_path<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    path.as_ref().split_last().map(|(name, parent)| parent.join(format!("{}.gz", name)))
+}
+
+pub struct ReadDir<'a, T: 'a + FSRead<'a>> {
+    inner: T::ReadDir,
+    parent: &'a Decompress<T>,
+}
+
+impl<'a, T: FSRead<'a>> Iterator for ReadDir<'a, T> {
+    type Item = QPath<'a, Decompress<T>>;
+
+    fn next(&mut self) -> Option<QPath<'a, Decompress<T>>> {
+        loop {
+            let entry = self.inner.next()?;
+            if let Some(p) = self.parent.listed(entry) {
+                return Some(self.parent.qualified(p));
+            }
+        }
+    }
+}
+
+/// A file opened through `Decompress`, either plain or gzipped.
+pub enum DecompressFile<R> {
+    Plain(R),
+    Gzip(GzDecoder<R>),
+}
+
+impl<R: io::Read> io::Read for DecompressFile<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            DecompressFile::Plain(ref mut r) => r.read(buf),
+            DecompressFile::Gzip(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Decompress<T> {
+    type ReadFile = DecompressFile<T::ReadFile>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<DecompressFile<T::ReadFile>> {
+        match self.compressed(&path) {
+            Some(gz) => self.inner.open(gz).map(|f| DecompressFile::Gzip(GzDecoder::new(f))),
+            None => self.inner.open(path).map(DecompressFile::Plain),
+        }
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        let gz = match self.compressed(&path) {
+            Some(gz) => gz,
+            None => return self.inner.metadata(path),
+        };
+        // the uncompressed size isn't stored anywhere reliable
+        let meta = self.inner.metadata(&gz)?;
+        let mut f = GzDecoder::new(self.inner.open(&gz)?);
+        let len = io::copy(&mut f, &mut io::sink()).map_err(|e| Error::new("stat", &path, e))?;
+        Ok(Metadata::new(FileType::File, len, meta.modified()))
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        match self.compressed(&path) {
+            Some(_) => Ok(FileType::File),
+            None => self.inner.file_type(path),
+        }
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self.compressed(&path) {
+            Some(gz) => self.inner.is_symlink(gz),
+            None => self.inner.is_symlink(path),
+        }
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        match self.compressed(&path) {
+            Some(gz) => self.inner.permissions(gz),
+            None => self.inner.permissions(path),
+        }
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        if let Some((name, parent)) = path.as_ref().split_last() {
+            if name != "." && name != ".." {
+                // keep the name this filesystem knows the file by
+                if let Ok(parent) = self.inner.canonicalize(parent) {
+                    let full = parent.join(name);
+                    if self.compressed(&full).is_some() {
+                        return Ok(full);
+                    }
+                }
+            }
+        }
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = ReadDir<'a, T>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a, T>> {
+        self.inner.read_dir(path).map(|r| ReadDir { inner: r, parent: self })
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        let entries = self.inner.read_dir_all(path)?;
+        Ok(entries.into_iter().filter_map(|e| self.listed(e)).map(|p| self.qualified(p)).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.filter_map(move |f| match f {
+            Ok(p) => self.listed(p).map(|p| Ok(self.qualified(p))),
+            Err(e) => Some(Err(e)),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, Native};
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::{Read, Write};
+    use tempdir::TempDir;
+
+    #[test]
+    fn decompress_gzip() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        {
+            let mut enc = GzEncoder::new(n.create("a.txt.gz").unwrap(), Compression::default());
+            enc.write_all(b"compressed").unwrap();
+            enc.finish().unwrap();
+        }
+        n.create("b.txt").unwrap().write_all(b"plain").unwrap();
+
+        let d = Decompress::new(n);
+        let mut s = String::new();
+        d.open("a.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "compressed");
+        let mut s = String::new();
+        d.open("b.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "plain");
+        assert!(d.is_file("a.txt"));
+        assert!(d.open("c.txt").is_err());
+
+        let mut names: Vec<PathBuf> = d.read_dir("").unwrap().map(|p| p.path().to_path_buf()).collect();
+        names.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        assert_eq!(names, vec!["a.txt", "b.txt"]);
+
+        assert_eq!(d.metadata("a.txt").unwrap().len(), 10);
+        assert_eq!(d.metadata("b.txt").unwrap().len(), 5);
+        assert!(!d.permissions("a.txt").unwrap().readonly());
+        assert_eq!(d.canonicalize("./a.txt").unwrap(), "a.txt");
+    }
+
+    #[test]
+    fn decompress_both() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        {
+            let mut enc = GzEncoder::new(n.create("a.gz").unwrap(), Compression::default());
+            enc.write_all(b"compressed").unwrap();
+            enc.finish().unwrap();
+        }
+        n.create("a").unwrap().write_all(b"stale").unwrap();
+        n.create_dir("d.gz").unwrap();
+
+        let d = Decompress::new(n);
+        let mut s = String::new();
+        d.open("a").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "compressed");
+
+        let mut names: Vec<PathBuf> = d.read_dir("").unwrap().map(|p| p.path().to_path_buf()).collect();
+        names.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        assert_eq!(names, vec!["a", "d.gz"]);
+        assert_eq!(d.read_dir_all("").unwrap().len(), 2);
+        let flat: Vec<_> = d.read_dir_recursive_flat("").unwrap().map(|p| p.unwrap().path().to_path_buf()).collect();
+        assert_eq!(flat, vec!["a"]);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use sha2::{Digest, Sha256};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Metadata, OpenOptions, Permissions, QPath, Requalify};
+
+/// A filesystem wrapper that hard-links files with identical contents.
+///
+/// `copy_dedup` hashes each file it copies and remembers where the
+/// copy went. Copying the same contents again links to the earlier
+/// copy instead of writing the data a second time. Paths written,
+/// moved or removed through the wrapper are forgotten, but changes
+/// made behind its back are not noticed. Writing in place to a linked
+/// file changes every path sharing it, so an earlier copy is hashed
+/// again before anything is linked to it.
+pub struct Dedup<T> {
+    inner: T,
+    index: Mutex<HashMap<[u8; 32], PathBuf>>,
+}
+
+impl<T> Dedup<T> {
+    pub fn new(inner: T) -> Dedup<T> {
+        Dedup { inner: inner, index: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    // stop linking to a path whose contents may have changed
+    fn forget<P: AsRef<Path>>(&self, path: P) {
+        self.index.lock().unwrap().retain(|_, p| p != path.as_ref());
+    }
+}
+
+impl<'a, T: FSWrite<'a> + FSRead<'a>> Dedup<T> {
+    /// Copy a file, hard-linking to an earlier copy with the same
+    /// contents if there is one.
+    ///
+    /// If linking fails, for example because the earlier copy has gone
+    /// or the filesystem has no hard links, the data is copied instead.
+    pub fn copy_dedup<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        let digest = self.digest(&from)?;
+
+        let existing = self.index.lock().unwrap().get(&digest).cloned();
+        // a write through another link may have changed it since
+        if let Some(existing) = existing.filter(|p| self.digest(p).ok() == Some(digest)) {
+            if existing == to.as_ref() {
+                return Ok(());
+            }
+            if self.inner.is_file(&to) {
+                self.inner.remove_file(&to)?;
+            }
+            if self.inner.hard_link(&existing, &to).is_ok() {
+                return Ok(());
+            }
+        }
+
+        self.forget(&to);
+        self.inner.copy(from, &to)?;
+        self.index.lock().unwrap().insert(digest, to.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    fn digest<P: AsRef<Path>>(&self, path: P) -> Result<[u8; 32]> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut self.inner.open(&path)?, &mut hasher).map_err(|e| Error::new("copy", &path, e))?;
+        let mut digest = [0; 32];
+        digest.copy_from_slice(&hasher.finalize());
+        Ok(digest)
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Dedup<T> {
+    type ReadFile = T::ReadFile;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<T::ReadFile> {
+        self.inner.open(path)
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(T::ReadFile, Metadata)> {
+        self.inner.open_meta(path)
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.inner.file_type(path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, Dedup<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+impl<'a, T: FSWrite<'a>> FSWrite<'a> for Dedup<T> {
+    type WriteFile = T::WriteFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.forget(&path);
+        self.inner.create(path)
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.forget(&path);
+        self.inner.append(path)
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<T::WriteFile> {
+        self.forget(&path);
+        self.inner.open_options(path, opts)
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        self.forget(&path);
+        self.inner.truncate(path, len)
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.inner.set_permissions(path, perm)
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.inner.hard_link(existing, link)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.forget(&from);
+        self.forget(&to);
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.forget(&path);
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.create_dir(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, Native};
+    use std::io::{Read, Write};
+    use tempdir::TempDir;
+
+    #[cfg(unix)]
+    #[test]
+    fn dedup_links() {
+        use std::fs;
+        use std::os::unix::fs::MetadataExt;
+
+        let t = TempDir::new("riotest").unwrap();
+        let d = Dedup::new(Native::new(t.path()));
+        d.create_dir("in").unwrap();
+        d.create_dir("out").unwrap();
+        d.create("in/a").unwrap().write_all(b"tile").unwrap();
+        d.create("in/b").unwrap().write_all(b"tile").unwrap();
+        d.create("in/c").unwrap().write_all(b"other").unwrap();
+        d.copy_dedup("in/a", "out/a").unwrap();
+        d.copy_dedup("in/b", "out/b").unwrap();
+        d.copy_dedup("in/c", "out/c").unwrap();
+
+        let ino = |p: &str| fs::metadata(t.path().join(p)).unwrap().ino();
+        assert_eq!(ino("out/a"), ino("out/b"));
+        assert!(ino("out/a") != ino("in/a"));
+        assert!(ino("out/a") != ino("out/c"));
+        let mut s = String::new();
+        d.open("out/b").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "tile");
+
+        // a removed copy is no longer linked to
+        d.remove_file("out/a").unwrap();
+        d.remove_file("out/b").unwrap();
+        d.copy_dedup("in/a", "out/a").unwrap();
+        assert!(d.is_file("out/a"));
+
+        // writing through one link changes the other behind the index
+        d.copy_dedup("in/b", "out/b").unwrap();
+        d.append("out/b").unwrap().write_all(b"!").unwrap();
+        d.copy_dedup("in/a", "out/d").unwrap();
+        let mut s = String::new();
+        d.open("out/d").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "tile");
+    }
+}
@@ -0,0 +1,269 @@
+use std::{io, vec};
+use std::sync::Mutex;
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Permissions, QPath};
+use layers::{Layer, Layers};
+use mem::{MemFS, MemFile};
+
+/// A write operation recorded by `DryRun`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Operation {
+    Create(PathBuf),
+    Append(PathBuf),
+    Truncate(PathBuf, u64),
+    SetPermissions(PathBuf, Permissions),
+    HardLink(PathBuf, PathBuf),
+    Rename(PathBuf, PathBuf),
+    CreateDir(PathBuf),
+    RemoveFile(PathBuf),
+    RemoveDir(PathBuf),
+}
+
+/// A filesystem wrapper that records writes instead of performing them.
+///
+/// Writes land in an in-memory overlay, and reads see the overlay on
+/// top of the base filesystem, so a pipeline run against a `DryRun`
+/// behaves as it would for real. The base is only ever read. Every
+/// write is also logged, and `operations` lists what would have been
+/// done.
+pub struct DryRun<'a, T: 'a + ?Sized> {
+    layers: Layers<'a, T>,
+    overlay: MemFS,
+    ops: Mutex<Vec<Operation>>,
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+impl<'a, T: ?Sized> DryRun<'a, T> {
+    pub fn new(base: &'a T) -> DryRun<'a, T> {
+        DryRun {
+            layers: Layers::new(base),
+            overlay: MemFS::new(),
+            ops: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get_ref(&self) -> &'a T {
+        self.layers.base
+    }
+
+    /// The write operations performed so far, in order.
+    pub fn operations(&self) -> Vec<Operation> {
+        self.ops.lock().unwrap().clone()
+    }
+
+    fn record(&self, op: Operation) {
+        self.ops.lock().unwrap().push(op);
+    }
+}
+
+/// A file opened through `DryRun`, from either the overlay or the base.
+pub enum DryRunFile<R> {
+    Base(R),
+    Overlay(io::Cursor<Vec<u8>>),
+}
+
+impl<R: io::Read> io::Read for DryRunFile<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            DryRunFile::Base(ref mut r) => r.read(buf),
+            DryRunFile::Overlay(ref mut r) => r.read(buf),
+        }
+    }
+}
+
+pub struct ReadDir<'a, T: 'a + ?Sized> {
+    iter: vec::IntoIter<PathBuf>,
+    parent: &'a DryRun<'a, T>,
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> Iterator for ReadDir<'a, T> {
+    type Item = QPath<'a, DryRun<'a, T>>;
+
+    fn next(&mut self) -> Option<QPath<'a, DryRun<'a, T>>> {
+        self.iter.next().map(|p| self.parent.qualified(p))
+    }
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> FSRead<'a> for DryRun<'a, T> {
+    type ReadFile = DryRunFile<T::ReadFile>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<DryRunFile<T::ReadFile>> {
+        match self.layers.layer(&self.overlay, &path) {
+            Layer::Upper => self.overlay.open(path).map(DryRunFile::Overlay),
+            Layer::Base => self.layers.base.open(path).map(DryRunFile::Base),
+            Layer::Hidden => Err(error("open", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.layers.file_type(&self.overlay, path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        match self.layers.layer(&self.overlay, &path) {
+            Layer::Upper => self.overlay.is_symlink(path),
+            Layer::Base => self.layers.base.is_symlink(path),
+            Layer::Hidden => false,
+        }
+    }
+
+    type ReadDir = ReadDir<'a, T>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a, T>> {
+        let children = self.layers.children(&self.overlay, path)?;
+        Ok(ReadDir { iter: children.into_iter(), parent: self })
+    }
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> FSWrite<'a> for DryRun<'a, T> {
+    type WriteFile = MemFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<MemFile> {
+        let f = self.layers.create(&self.overlay, &path)?;
+        self.record(Operation::Create(path.as_ref().to_path_buf()));
+        Ok(f)
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<MemFile> {
+        self.layers.copy_up(&self.overlay, "append to", &path)?;
+        let f = self.overlay.append(&path)?;
+        self.record(Operation::Append(path.as_ref().to_path_buf()));
+        Ok(f)
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        self.layers.copy_up(&self.overlay, "truncate", &path)?;
+        self.overlay.truncate(&path, len)?;
+        self.record(Operation::Truncate(path.as_ref().to_path_buf(), len));
+        Ok(())
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        if !self.exists(&path) {
+            return Err(error("set permissions on", path, io::ErrorKind::NotFound, "File not found."));
+        }
+        self.record(Operation::SetPermissions(path.as_ref().to_path_buf(), perm));
+        Ok(())
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.layers.hard_link(&self.overlay, &existing, &link)?;
+        self.record(Operation::HardLink(existing.as_ref().to_path_buf(), link.as_ref().to_path_buf()));
+        Ok(())
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.layers.rename(&self.overlay, &from, &to)?;
+        self.record(Operation::Rename(from.as_ref().to_path_buf(), to.as_ref().to_path_buf()));
+        Ok(())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.layers.remove_file(&self.overlay, &path)?;
+        self.record(Operation::RemoveFile(path.as_ref().to_path_buf()));
+        Ok(())
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.layers.remove_dir(&self.overlay, &path)?;
+        self.record(Operation::RemoveDir(path.as_ref().to_path_buf()));
+        Ok(())
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.layers.create_dir(&self.overlay, &path)?;
+        self.record(Operation::CreateDir(path.as_ref().to_path_buf()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS};
+    use std::io::{Read, Write};
+
+    fn read<'a, T: FSRead<'a>>(fs: &T, path: &str) -> String {
+        let mut s = String::new();
+        fs.open(path).unwrap().read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn dry_run() {
+        let base = MemFS::new();
+        base.create_dir("d").unwrap();
+        base.create("d/a").unwrap().write_all(b"base").unwrap();
+        base.create("b").unwrap().write_all(b"b").unwrap();
+
+        let dry = DryRun::new(&base);
+        dry.create("new").unwrap().write_all(b"new").unwrap();
+        dry.create("d/c").unwrap().write_all(b"c").unwrap();
+        dry.append("d/a").unwrap().write_all(b"+").unwrap();
+        assert_eq!(read(&dry, "new"), "new");
+        assert_eq!(read(&dry, "d/a"), "base+");
+        dry.remove_file("b").unwrap();
+        assert!(!dry.exists("b"));
+        dry.rename_create("d", "x/d").unwrap();
+        assert_eq!(read(&dry, "x/d/a"), "base+");
+        assert!(!dry.exists("d/a"));
+
+        let root: Vec<PathBuf> = dry.read_dir("").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(root, vec!["new", "x"]);
+        let d: Vec<PathBuf> = dry.read_dir("x/d").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(d, vec!["x/d/a", "x/d/c"]);
+
+        assert_eq!(dry.operations(), vec![
+            Operation::Create(PathBuf::from("new")),
+            Operation::Create(PathBuf::from("d/c")),
+            Operation::Append(PathBuf::from("d/a")),
+            Operation::RemoveFile(PathBuf::from("b")),
+            Operation::CreateDir(PathBuf::from("x")),
+            Operation::Rename(PathBuf::from("d"), PathBuf::from("x/d")),
+        ]);
+
+        // the base is untouched
+        assert_eq!(read(&base, "d/a"), "base");
+        assert_eq!(read(&base, "b"), "b");
+        assert!(!base.exists("new"));
+        assert!(!base.exists("d/c"));
+        assert!(!base.exists("x"));
+    }
+
+    #[test]
+    fn dry_run_remove_dir() {
+        let base = MemFS::new();
+        base.create_dir("d").unwrap();
+        base.create("d/a").unwrap();
+
+        let dry = DryRun::new(&base);
+        assert!(dry.remove_dir("d").is_err());
+        dry.remove_file("d/a").unwrap();
+        dry.remove_dir("d").unwrap();
+        assert!(!dry.exists("d"));
+        dry.create_dir("d").unwrap();
+        assert!(!dry.exists("d/a"));
+        assert_eq!(dry.read_dir("d").unwrap().count(), 0);
+        assert!(base.is_file("d/a"));
+    }
+
+    #[test]
+    fn dry_run_rename_partial() {
+        let base = MemFS::new();
+        base.create_dir("d").unwrap();
+        base.create("d/a").unwrap().write_all(b"a").unwrap();
+        base.create("d/b").unwrap().write_all(b"b").unwrap();
+
+        // d is mirrored in the overlay to hold c, but a and b aren't
+        let dry = DryRun::new(&base);
+        dry.create("d/c").unwrap().write_all(b"c").unwrap();
+        dry.rename("d", "x").unwrap();
+        let x: Vec<PathBuf> = dry.read_dir("x").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(x, vec!["x/a", "x/b", "x/c"]);
+        assert_eq!(read(&dry, "x/a"), "a");
+        assert!(!dry.exists("d"));
+    }
+}
@@ -0,0 +1,133 @@
+use std::{io, slice};
+use include_dir::{Dir, DirEntry};
+use path::{Path, PathBuf};
+use fs::{FSRead, Error, Result, FileType, Permissions, QPath};
+
+/// A read-only filesystem compiled into the binary.
+///
+/// This wraps a directory embedded with the `include_dir!` macro, so
+/// default assets can be read through the same traits as a `Native`
+/// filesystem on disk. Directories are synthesized from the embedded
+/// layout.
+///
+/// `EmbeddedFS` only holds static data, so it is `Send` and `Sync`.
+pub struct EmbeddedFS {
+    root: &'static Dir<'static>,
+}
+
+impl EmbeddedFS {
+    pub fn new(root: &'static Dir<'static>) -> EmbeddedFS {
+        EmbeddedFS { root: root }
+    }
+
+    fn entries(&self) -> &'static [DirEntry<'static>] {
+        self.root.entries()
+    }
+
+    fn entry<P: AsRef<Path>>(&self, path: P) -> Option<Entry> {
+        let mut current = Entry::Dir(self.entries());
+        for part in path.as_ref() {
+            let children = match current {
+                Entry::Dir(children) => children,
+                Entry::File(_) => return None,
+            };
+            let found = children.iter().find(|e| {
+                e.path().file_name().and_then(|n| n.to_str()) == Some(part.as_str())
+            });
+            current = match found {
+                Some(&DirEntry::Dir(ref d)) => Entry::Dir(d.entries()),
+                Some(&DirEntry::File(ref f)) => Entry::File(f.contents()),
+                None => return None,
+            };
+        }
+        Some(current)
+    }
+}
+
+enum Entry {
+    Dir(&'static [DirEntry<'static>]),
+    File(&'static [u8]),
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+pub struct ReadDir<'a> {
+    iter: slice::Iter<'static, DirEntry<'static>>,
+    parent: &'a EmbeddedFS,
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = QPath<'a, EmbeddedFS>;
+
+    fn next(&mut self) -> Option<QPath<'a, EmbeddedFS>> {
+        self.iter.next().map(|e| self.parent.qualified(PathBuf::from(e.path().to_path_buf())))
+    }
+}
+
+impl<'a> FSRead<'a> for EmbeddedFS {
+    type ReadFile = io::Cursor<&'static [u8]>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Cursor<&'static [u8]>> {
+        match self.entry(&path) {
+            Some(Entry::File(contents)) => Ok(io::Cursor::new(contents)),
+            Some(Entry::Dir(_)) => Err(error("open", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("open", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        match self.entry(&path) {
+            Some(Entry::File(_)) => Ok(FileType::File),
+            Some(Entry::Dir(_)) => Ok(FileType::Dir),
+            None => Err(error("stat", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.file_type(path).map(|_| Permissions::new(true))
+    }
+
+    type ReadDir = ReadDir<'a>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a>> {
+        match self.entry(&path) {
+            Some(Entry::Dir(children)) => Ok(ReadDir { iter: children.iter(), parent: self }),
+            Some(Entry::File(_)) => Err(error("read directory", path, io::ErrorKind::Other, "Not a directory.")),
+            None => Err(error("read directory", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::FSRead;
+    use include_dir::{Dir, include_dir};
+    use std::io::Read;
+
+    static ASSETS: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/testdata/embedded");
+
+    #[test]
+    fn embedded_read() {
+        let e = EmbeddedFS::new(&ASSETS);
+        let mut s = String::new();
+        e.open("/sub/nested.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "nested\n");
+        assert!(e.is_file("hello.txt"));
+        assert!(e.is_dir("sub"));
+        assert!(e.is_dir("/"));
+        assert!(!e.exists("sub/missing.txt"));
+    }
+
+    #[test]
+    fn embedded_read_dir() {
+        let e = EmbeddedFS::new(&ASSETS);
+        let mut names: Vec<PathBuf> = e.read_dir("").unwrap().map(|p| p.path().to_path_buf()).collect();
+        names.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(names, vec!["hello.txt", "sub"]);
+        let nested: Vec<PathBuf> = e.read_dir("sub").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(nested, vec!["sub/nested.txt"]);
+    }
+}
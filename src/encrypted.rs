@@ -0,0 +1,327 @@
+use std::io;
+use std::sync::Arc;
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Metadata, Permissions, QPath, Requalify};
+
+// each file is a random nonce, then the ciphertext with its tag
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+fn seal(cipher: &ChaCha20Poly1305, data: &[u8]) -> io::Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = cipher.encrypt(&nonce, data).map_err(|_| io::Error::new(io::ErrorKind::Other, "Encryption failed."))?;
+    let mut out = Vec::with_capacity(NONCE_LEN + sealed.len());
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+fn unseal(cipher: &ChaCha20Poly1305, data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < NONCE_LEN + TAG_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "File is too short."));
+    }
+    let (nonce, sealed) = data.split_at(NONCE_LEN);
+    cipher.decrypt(Nonce::from_slice(nonce), sealed).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Decryption failed."))
+}
+
+/// A filesystem wrapper that encrypts file contents.
+///
+/// Files are sealed with ChaCha20-Poly1305 under the given key, each
+/// with its own random nonce. File and directory names are left as
+/// they are. Opening a file that was written with another key, or
+/// that has been tampered with, is an `InvalidData` error.
+///
+/// Whole files are encrypted and decrypted at once, so they are held
+/// in memory while open.
+pub struct Encrypted<T> {
+    // shared with files being appended to, which replace the old file
+    // when they are finished
+    inner: Arc<T>,
+    cipher: ChaCha20Poly1305,
+}
+
+impl<T> Encrypted<T> {
+    pub fn new(inner: T, key: &[u8; 32]) -> Encrypted<T> {
+        Encrypted { inner: Arc::new(inner), cipher: ChaCha20Poly1305::new(Key::from_slice(key)) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<'a, T: FSWrite<'a>> Encrypted<T> {
+    fn file<P: AsRef<Path>>(&self, path: P, f: T::WriteFile, staging: Option<PathBuf>, data: Vec<u8>) -> EncryptedFile<'a, T> {
+        EncryptedFile {
+            fs: self.inner.clone(),
+            inner: Some(f),
+            cipher: self.cipher.clone(),
+            path: path.as_ref().to_path_buf(),
+            staging: staging,
+            data: data,
+        }
+    }
+}
+
+impl<'a, T: FSRead<'a>> Encrypted<T> {
+    fn decrypt<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut self.inner.open(&path)?, &mut data).map_err(|e| Error::new(op, &path, e))?;
+        unseal(&self.cipher, &data).map_err(|e| Error::new(op, path, e))
+    }
+}
+
+// a sibling of path to write a replacement to, before renaming it over
+fn staging<P: AsRef<Path>>(path: P) -> PathBuf {
+    match path.as_ref().split_last() {
+        Some((name, parent)) => parent.join(format!(".{}.rio-tmp", name)),
+        None => PathBuf::from(".rio-tmp"),
+    }
+}
+
+/// A writable file on an `Encrypted` filesystem.
+///
+/// The whole file is encrypted at once, so nothing reaches the
+/// filesystem underneath until `finish` is called or the file is
+/// dropped. Flushing does nothing. Use `finish` to see any errors.
+///
+/// When appending, the old and new contents are written to a hidden
+/// file alongside, which replaces the original only once it is
+/// complete, so a failed append leaves the original as it was.
+pub struct EncryptedFile<'a, T: FSWrite<'a>> {
+    fs: Arc<T>,
+    inner: Option<T::WriteFile>,
+    cipher: ChaCha20Poly1305,
+    path: PathBuf,
+    // where the file is being written, if not at path
+    staging: Option<PathBuf>,
+    data: Vec<u8>,
+}
+
+impl<'a, T: FSWrite<'a>> EncryptedFile<'a, T> {
+    /// Encrypt and write out the contents.
+    pub fn finish(mut self) -> Result<()> {
+        self.seal()
+    }
+
+    fn seal(&mut self) -> Result<()> {
+        if let Some(f) = self.inner.take() {
+            let res = self.write_out(f);
+            if let Some(staging) = self.staging.take() {
+                match res {
+                    Ok(()) => return self.fs.rename(staging, &self.path),
+                    Err(_) => {
+                        let _ = self.fs.remove_file(staging);
+                    }
+                }
+            }
+            return res;
+        }
+        Ok(())
+    }
+
+    fn write_out(&self, mut f: T::WriteFile) -> Result<()> {
+        let sealed = seal(&self.cipher, &self.data).map_err(|e| Error::new("create", &self.path, e))?;
+        io::Write::write_all(&mut f, &sealed).and_then(|_| io::Write::flush(&mut f)).map_err(|e| Error::new("create", &self.path, e))
+    }
+}
+
+impl<'a, T: FSWrite<'a>> io::Write for EncryptedFile<'a, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a, T: FSWrite<'a>> Drop for EncryptedFile<'a, T> {
+    fn drop(&mut self) {
+        let _ = self.seal();
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Encrypted<T> {
+    type ReadFile = io::Cursor<Vec<u8>>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Cursor<Vec<u8>>> {
+        self.decrypt("open", path).map(io::Cursor::new)
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(io::Cursor<Vec<u8>>, Metadata)> {
+        let (mut f, meta) = self.inner.open_meta(&path)?;
+        let mut data = Vec::new();
+        io::Read::read_to_end(&mut f, &mut data).map_err(|e| Error::new("open", &path, e))?;
+        let data = unseal(&self.cipher, &data).map_err(|e| Error::new("open", &path, e))?;
+        let meta = Metadata::new(meta.file_type(), data.len() as u64, meta.modified());
+        Ok((io::Cursor::new(data), meta))
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.inner.file_type(path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        let meta = self.inner.metadata(path)?;
+        if meta.is_dir() {
+            return Ok(meta);
+        }
+        let len = meta.len().saturating_sub((NONCE_LEN + TAG_LEN) as u64);
+        Ok(Metadata::new(meta.file_type(), len, meta.modified()))
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, Encrypted<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+impl<'a, T: FSRead<'a> + FSWrite<'a>> FSWrite<'a> for Encrypted<T> {
+    type WriteFile = EncryptedFile<'a, T>;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<EncryptedFile<'a, T>> {
+        let f = self.inner.create(&path)?;
+        Ok(self.file(path, f, None, Vec::new()))
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<EncryptedFile<'a, T>> {
+        // the old contents are sealed again along with the new, and
+        // the old file is kept until that is done
+        let data = self.decrypt("append", &path)?;
+        let staging = staging(&path);
+        let f = self.inner.create(&staging)?;
+        Ok(self.file(path, f, Some(staging), data))
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        let mut data = self.decrypt("truncate", &path)?;
+        data.resize(len as usize, 0);
+        let staging = staging(&path);
+        let f = self.inner.create(&staging)?;
+        self.file(path, f, Some(staging), data).finish()
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.inner.set_permissions(path, perm)
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.inner.hard_link(existing, link)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.create_dir(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS};
+    use std::io::{Read, Write};
+
+    fn raw(m: &MemFS, path: &str) -> Vec<u8> {
+        let mut v = Vec::new();
+        m.open(path).unwrap().read_to_end(&mut v).unwrap();
+        v
+    }
+
+    #[test]
+    fn encrypted_roundtrip() {
+        let e = Encrypted::new(MemFS::new(), &[7; 32]);
+        e.create("secret").unwrap().write_all(b"attack at dawn").unwrap();
+        let on_disk = raw(e.get_ref(), "secret");
+        assert_eq!(on_disk.len(), 14 + NONCE_LEN + TAG_LEN);
+        assert!(!on_disk.windows(6).any(|w| w == b"attack"));
+
+        let mut s = String::new();
+        e.open("secret").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "attack at dawn");
+        assert_eq!(e.metadata("secret").unwrap().len(), 14);
+        let (mut f, meta) = e.open_meta("secret").unwrap();
+        let mut v = Vec::new();
+        f.read_to_end(&mut v).unwrap();
+        assert_eq!(meta.len(), v.len() as u64);
+
+        e.append("secret").unwrap().write_all(b"!").unwrap();
+        e.truncate("secret", 6).unwrap();
+        let mut s = String::new();
+        e.open("secret").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "attack");
+
+        // the same contents get a fresh nonce each time
+        e.create("again").unwrap().write_all(b"attack").unwrap();
+        assert!(raw(e.get_ref(), "again") != raw(e.get_ref(), "secret"));
+    }
+
+    #[test]
+    fn encrypted_wrong_key() {
+        let e = Encrypted::new(MemFS::new(), &[7; 32]);
+        e.create("secret").unwrap().write_all(b"attack at dawn").unwrap();
+        let m = MemFS::new();
+        m.create("secret").unwrap().write_all(&raw(e.get_ref(), "secret")).unwrap();
+        m.create("short").unwrap().write_all(b"plain").unwrap();
+
+        let wrong = Encrypted::new(m, &[8; 32]);
+        let err = wrong.open("secret").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(wrong.open("short").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn encrypted_append_keeps_original() {
+        let e = Encrypted::new(MemFS::new(), &[7; 32]);
+        e.create("secret").unwrap().write_all(b"attack").unwrap();
+        let mut f = e.append("secret").unwrap();
+        f.write_all(b" at dawn").unwrap();
+        // until the append is finished the old file is untouched
+        let mut s = String::new();
+        e.open("secret").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "attack");
+        f.finish().unwrap();
+        let mut s = String::new();
+        e.open("secret").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "attack at dawn");
+        assert_eq!(e.read_dir("").unwrap().count(), 1);
+    }
+}
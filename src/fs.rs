@@ -1,12 +1,101 @@
-use std::{io, result};
+use std::{io, fmt, fs, error, result, cmp, thread, vec};
+use std::io::{BufRead, Read, Seek};
+use std::sync::Mutex;
+use std::borrow::Cow;
+use std::hash::{Hash, Hasher};
+use std::time::SystemTime;
+#[cfg(feature = "regex")]
+use regex::Regex;
+#[cfg(feature = "sha2")]
+use sha2::{Digest, Sha256};
 use path::{Path, PathBuf};
+use tree::Tree;
 
-pub use std::io::{Error};
+/// The error type for all filesystem IO.
+///
+/// This wraps an `io::Error`, and when available also records the
+/// path and the operation that failed.
+pub struct Error {
+    inner: io::Error,
+    op: Option<&'static str>,
+    path: Option<PathBuf>,
+}
+
+impl Error {
+    /// Create an error for operation `op` failing on `path`.
+    pub fn new<P: AsRef<Path>>(op: &'static str, path: P, err: io::Error) -> Error {
+        Error { inner: err, op: Some(op), path: Some(path.as_ref().to_path_buf()) }
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        self.inner.kind()
+    }
+
+    /// The path that caused this error, if known.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(|p| p.as_path())
+    }
+
+    /// The name of the operation that failed, if known.
+    pub fn operation(&self) -> Option<&str> {
+        self.op
+    }
+
+    pub fn io_error(&self) -> &io::Error {
+        &self.inner
+    }
+
+    pub fn into_io_error(self) -> io::Error {
+        self.inner
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error { inner: err, op: None, path: None }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        if err.path.is_some() {
+            io::Error::new(err.kind(), err)
+        } else {
+            err.inner
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match (self.op, &self.path) {
+            (Some(op), &Some(ref path)) => write!(formatter, "failed to {} {}: {}", op, path.as_str(), self.inner),
+            _ => fmt::Display::fmt(&self.inner, formatter),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_struct("Error")
+            .field("op", &self.op)
+            .field("path", &self.path)
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.inner)
+    }
+}
 
 /// The result type for all filesystem IO.
 pub type Result<T> = result::Result<T, Error>;
 
 /// Possible file types.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum FileType {
     Dir,
     File,
@@ -54,10 +143,527 @@ impl<'a, T: ?Sized + FSRead<'a>> Iterator for DirEntries<'a, T> {
     }
 }
 
+// anything that isn't a directory reads and writes like a file
+impl From<fs::FileType> for FileType {
+    fn from(t: fs::FileType) -> FileType {
+        if t.is_dir() { FileType::Dir } else { FileType::File }
+    }
+}
+
+/// Information about a file or directory.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    file_type: FileType,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl Metadata {
+    pub fn new(file_type: FileType, len: u64, modified: Option<SystemTime>) -> Metadata {
+        Metadata { file_type: file_type, len: len, modified: modified }
+    }
+
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.file_type.is_dir()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type.is_file()
+    }
+
+    /// The size of a file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether a file has no bytes in it.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The last modification time, if the backend keeps one.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+}
+
+/// Options for opening a file for writing, like `std::fs::OpenOptions`.
+///
+/// These are passed to `FSWrite::open_options`. For example,
+/// `OpenOptions::new().write(true).create_new(true)` creates a file
+/// only if it doesn't exist yet.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct OpenOptions {
+    pub(crate) read: bool,
+    pub(crate) write: bool,
+    pub(crate) append: bool,
+    pub(crate) create: bool,
+    pub(crate) create_new: bool,
+    pub(crate) truncate: bool,
+}
+
+impl OpenOptions {
+    /// Options with every flag unset.
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    /// Also allow reading, where the backend's files support it.
+    pub fn read(mut self, read: bool) -> OpenOptions {
+        self.read = read;
+        self
+    }
+
+    pub fn write(mut self, write: bool) -> OpenOptions {
+        self.write = write;
+        self
+    }
+
+    /// Write to the end of the file, after what is already there.
+    pub fn append(mut self, append: bool) -> OpenOptions {
+        self.append = append;
+        self
+    }
+
+    /// Create the file if it doesn't exist.
+    pub fn create(mut self, create: bool) -> OpenOptions {
+        self.create = create;
+        self
+    }
+
+    /// Create the file, failing if it already exists. This overrides
+    /// `create` and `truncate`.
+    pub fn create_new(mut self, create_new: bool) -> OpenOptions {
+        self.create_new = create_new;
+        self
+    }
+
+    /// Empty the file if it already exists.
+    pub fn truncate(mut self, truncate: bool) -> OpenOptions {
+        self.truncate = truncate;
+        self
+    }
+
+    // refuse combinations that make no sense, as std does
+    pub(crate) fn check<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let msg = if !self.write && !self.append {
+            "Must write or append."
+        } else if self.truncate && self.append {
+            "Can't both truncate and append."
+        } else {
+            return Ok(());
+        };
+        Err(Error::new("open", path, io::Error::new(io::ErrorKind::InvalidInput, msg)))
+    }
+}
+
+/// A depth-first iterator over everything under a directory.
+///
+/// This is produced by `FSRead::walk`.
+pub struct Walk<'a, T: 'a + ?Sized + FSRead<'a>> {
+    stack: Vec<T::ReadDir>,
+    // a directory just yielded, to descend into on the next call
+    pending: Option<QPath<'a, T>>,
+    // whether to descend into symlinks to directories
+    follow_links: bool,
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> Walk<'a, T> {
+    // a walk that yields symlinks without descending into them
+    fn no_follow<P: AsRef<Path>>(fs: &'a T, path: P) -> Result<Walk<'a, T>> {
+        fs.read_dir(path).map(|r| Walk { stack: vec![r], pending: None, follow_links: false })
+    }
+
+    // the next entry with its type, or None for a symlink that isn't
+    // followed, whose target isn't looked up at all
+    fn next_typed(&mut self) -> Option<Result<(QPath<'a, T>, Option<FileType>)>> {
+        if let Some(dir) = self.pending.take() {
+            match dir.read_dir() {
+                Ok(r) => self.stack.push(r),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        loop {
+            let entry = match self.stack.last_mut() {
+                Some(r) => r.next(),
+                None => return None,
+            };
+            match entry {
+                Some(entry) => {
+                    if !self.follow_links && entry.is_symlink() {
+                        return Some(Ok((entry, None)));
+                    }
+                    let file_type = match entry.file_type() {
+                        Ok(t) => t,
+                        Err(e) => return Some(Err(e)),
+                    };
+                    if file_type.is_dir() {
+                        self.pending = Some(entry.clone());
+                    }
+                    return Some(Ok((entry, Some(file_type))));
+                }
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> Iterator for Walk<'a, T> {
+    type Item = Result<QPath<'a, T>>;
+
+    fn next(&mut self) -> Option<Result<QPath<'a, T>>> {
+        self.next_typed().map(|r| r.map(|(entry, _)| entry))
+    }
+}
+
+type SortFn<'a, T> = Box<dyn FnMut(&QPath<'a, T>, &QPath<'a, T>) -> cmp::Ordering + 'a>;
+type FilterFn<'a, T> = Box<dyn FnMut(&QPath<'a, T>) -> bool + 'a>;
+
+/// A configurable depth-first walk, in the style of `walkdir`.
+///
+/// This is produced by `FSRead::walk_builder`. Depths count from the
+/// directory being walked, which is never yielded itself: its entries
+/// are at depth 1, their entries at depth 2, and so on.
+pub struct WalkBuilder<'a, T: 'a + ?Sized + FSRead<'a>> {
+    fs: &'a T,
+    root: PathBuf,
+    min_depth: usize,
+    max_depth: usize,
+    sort: Option<SortFn<'a, T>>,
+    filter: Option<FilterFn<'a, T>>,
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> WalkBuilder<'a, T> {
+    /// Only yield entries at least this deep. Shallower directories
+    /// are still descended into.
+    pub fn min_depth(mut self, depth: usize) -> WalkBuilder<'a, T> {
+        self.min_depth = depth;
+        self
+    }
+
+    /// Don't yield or descend into anything deeper than this.
+    pub fn max_depth(mut self, depth: usize) -> WalkBuilder<'a, T> {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Visit the entries of each directory in the order given by `cmp`,
+    /// rather than the order the backend lists them in.
+    pub fn sort_by<F>(mut self, cmp: F) -> WalkBuilder<'a, T>
+        where F: FnMut(&QPath<'a, T>, &QPath<'a, T>) -> cmp::Ordering + 'a
+    {
+        self.sort = Some(Box::new(cmp));
+        self
+    }
+
+    /// Skip entries for which `predicate` returns false. A skipped
+    /// directory is not descended into, so nothing under it is
+    /// yielded either. This applies at every depth, including those
+    /// above `min_depth`.
+    pub fn filter_entry<F>(mut self, predicate: F) -> WalkBuilder<'a, T>
+        where F: FnMut(&QPath<'a, T>) -> bool + 'a
+    {
+        self.filter = Some(Box::new(predicate));
+        self
+    }
+
+    /// Start the walk, listing the top directory.
+    pub fn build(self) -> Result<Walker<'a, T>> {
+        let mut walker = Walker {
+            stack: Vec::new(),
+            pending: None,
+            min_depth: self.min_depth,
+            max_depth: self.max_depth,
+            sort: self.sort,
+            filter: self.filter,
+        };
+        if walker.max_depth > 0 {
+            let top = walker.list(&self.fs.qualified(self.root))?;
+            walker.stack.push(top);
+        }
+        Ok(walker)
+    }
+}
+
+/// The iterator produced by `WalkBuilder::build`.
+///
+/// Like `Walk`, errors looking up or listing an entry are yielded in
+/// its place, and the walk carries on.
+pub struct Walker<'a, T: 'a + ?Sized + FSRead<'a>> {
+    stack: Vec<vec::IntoIter<QPath<'a, T>>>,
+    // a directory to descend into on the next call
+    pending: Option<QPath<'a, T>>,
+    min_depth: usize,
+    max_depth: usize,
+    sort: Option<SortFn<'a, T>>,
+    filter: Option<FilterFn<'a, T>>,
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> Walker<'a, T> {
+    fn list(&mut self, dir: &QPath<'a, T>) -> Result<vec::IntoIter<QPath<'a, T>>> {
+        let mut entries: Vec<QPath<'a, T>> = dir.read_dir()?.collect();
+        if let Some(ref mut cmp) = self.sort {
+            entries.sort_by(|a, b| cmp(a, b));
+        }
+        Ok(entries.into_iter())
+    }
+}
+
+impl<'a, T: ?Sized + FSRead<'a>> Iterator for Walker<'a, T> {
+    type Item = Result<QPath<'a, T>>;
+
+    fn next(&mut self) -> Option<Result<QPath<'a, T>>> {
+        loop {
+            if let Some(dir) = self.pending.take() {
+                match self.list(&dir) {
+                    Ok(r) => self.stack.push(r),
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            let depth = self.stack.len();
+            let entry = match self.stack.last_mut() {
+                Some(r) => r.next(),
+                None => return None,
+            };
+            let entry = match entry {
+                Some(entry) => entry,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            if let Some(ref mut keep) = self.filter {
+                if !keep(&entry) {
+                    continue;
+                }
+            }
+            match entry.file_type() {
+                Ok(FileType::Dir) if depth < self.max_depth => self.pending = Some(entry.clone()),
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            if depth >= self.min_depth {
+                return Some(Ok(entry));
+            }
+        }
+    }
+}
+
+/// File permissions.
+///
+/// Every backend understands the read-only flag. On unix, backends
+/// may also report and accept a full permission mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Permissions {
+    readonly: bool,
+    mode: Option<u32>,
+}
+
+impl Permissions {
+    pub fn new(readonly: bool) -> Permissions {
+        Permissions { readonly: readonly, mode: None }
+    }
+
+    pub fn readonly(&self) -> bool {
+        self.readonly
+    }
+
+    /// Set the read-only flag. This forgets any unix mode.
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+        self.mode = None;
+    }
+
+    /// Create permissions from a unix mode, like 0o644.
+    #[cfg(unix)]
+    pub fn from_mode(mode: u32) -> Permissions {
+        Permissions { readonly: mode & 0o222 == 0, mode: Some(mode & 0o7777) }
+    }
+
+    /// The unix mode, if the backend reported one.
+    #[cfg(unix)]
+    pub fn mode(&self) -> Option<u32> {
+        self.mode
+    }
+}
+
+/// Adaptors for iterators over directory entries.
+///
+/// This is implemented for `DirEntries` and every backend's `ReadDir`.
+pub trait DirEntriesExt<'a, T: 'a + ?Sized + FSRead<'a>> : Iterator<Item=QPath<'a, T>> + Sized {
+    /// Only yield entries that are files.
+    ///
+    /// Each entry's type is looked up as it is reached. Entries whose
+    /// type can't be determined are skipped.
+    fn files(self) -> OfType<Self> {
+        OfType { inner: self, dirs: false }
+    }
+
+    /// Only yield entries that are directories.
+    ///
+    /// As with `files`, entries whose type can't be determined are
+    /// skipped.
+    fn dirs(self) -> OfType<Self> {
+        OfType { inner: self, dirs: true }
+    }
+
+    /// Only yield entries whose file name matches a regex.
+    ///
+    /// The regex is matched against the last component of each
+    /// entry, not the full path.
+    #[cfg(feature = "regex")]
+    fn matching(self, re: &Regex) -> Matching<Self> {
+        Matching { inner: self, re: re.clone() }
+    }
+
+    /// Skip hidden entries, those whose file name starts with a dot.
+    fn visible(self) -> Visible<Self> {
+        Visible { inner: self }
+    }
+
+    /// Collect every entry and yield them sorted by path.
+    ///
+    /// Backends list directories in whatever order they like, so use
+    /// this wherever the output needs to be reproducible.
+    fn sorted(self) -> vec::IntoIter<QPath<'a, T>> {
+        let mut v: Vec<_> = self.collect();
+        v.sort();
+        v.into_iter()
+    }
+}
+
+impl<'a, T: ?Sized + FSRead<'a>, I: Iterator<Item=QPath<'a, T>>> DirEntriesExt<'a, T> for I {}
+
+/// An iterator over directory entries of one type.
+///
+/// This is produced by `DirEntriesExt::files` and `DirEntriesExt::dirs`.
+pub struct OfType<I> {
+    inner: I,
+    dirs: bool,
+}
+
+impl<'a, T: ?Sized + FSRead<'a>, I: Iterator<Item=QPath<'a, T>>> Iterator for OfType<I> {
+    type Item = QPath<'a, T>;
+
+    fn next(&mut self) -> Option<QPath<'a, T>> {
+        let dirs = self.dirs;
+        self.inner.find(|p| {
+            p.file_type().map(|t| if dirs { t.is_dir() } else { t.is_file() }).unwrap_or(false)
+        })
+    }
+}
+
+/// An iterator over directory entries that aren't hidden.
+///
+/// This is produced by `DirEntriesExt::visible`.
+pub struct Visible<I> {
+    inner: I,
+}
+
+impl<'a, T: ?Sized + FSRead<'a>, I: Iterator<Item=QPath<'a, T>>> Iterator for Visible<I> {
+    type Item = QPath<'a, T>;
+
+    fn next(&mut self) -> Option<QPath<'a, T>> {
+        self.inner.find(|p| !p.path().is_hidden())
+    }
+}
+
+/// An iterator over directory entries with matching file names.
+///
+/// This is produced by `DirEntriesExt::matching` and
+/// `FSRead::read_dir_matching`.
+#[cfg(feature = "regex")]
+pub struct Matching<I> {
+    inner: I,
+    re: Regex,
+}
+
+#[cfg(feature = "regex")]
+impl<'a, T: ?Sized + FSRead<'a>, I: Iterator<Item=QPath<'a, T>>> Iterator for Matching<I> {
+    type Item = QPath<'a, T>;
+
+    fn next(&mut self) -> Option<QPath<'a, T>> {
+        let re = &self.re;
+        self.inner.find(|p| p.path().file_name().is_some_and(|n| re.is_match(n)))
+    }
+}
+
+/// An iterator that re-attaches directory entries to another filesystem.
+///
+/// This is useful for wrapper filesystems, which need to hand out
+/// entries from the filesystem they wrap as their own.
+pub struct Requalify<'a, I, T: 'a + ?Sized> {
+    inner: I,
+    parent: &'a T,
+}
+
+impl<'a, I, T: ?Sized> Requalify<'a, I, T> {
+    pub fn new(inner: I, parent: &'a T) -> Requalify<'a, I, T> {
+        Requalify { inner: inner, parent: parent }
+    }
+}
+
+impl<'a, I, T: ?Sized + FSRead<'a>> Iterator for Requalify<'a, I, T> where I: Iterator, I::Item: AsRef<Path> {
+    type Item = QPath<'a, T>;
+
+    fn next(&mut self) -> Option<QPath<'a, T>> {
+        self.inner.next().map(|p| self.parent.qualified(p))
+    }
+}
+
 /// A Qualified path, a path tied to a particular filesystem.
+///
+/// Equality, ordering and hashing only consider the path, by
+/// components, and not which filesystem it belongs to.
 pub struct QPath<'a, T: 'a + ?Sized> {
     path: PathBuf,
     parent: &'a T,
+    // a prefix removed by strip_prefix, still used to find the file
+    root: PathBuf,
+}
+
+impl<'a, T: ?Sized> Clone for QPath<'a, T> {
+    fn clone(&self) -> QPath<'a, T> {
+        QPath { path: self.path.clone(), parent: self.parent, root: self.root.clone() }
+    }
+}
+
+impl<'a, T: ?Sized> fmt::Debug for QPath<'a, T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.path, formatter)
+    }
+}
+
+impl<'a, 'b, T: ?Sized, U: ?Sized> PartialEq<QPath<'b, U>> for QPath<'a, T> {
+    fn eq(&self, other: &QPath<'b, U>) -> bool {
+        self.path == other.path
+    }
+}
+
+impl<'a, T: ?Sized> Eq for QPath<'a, T> {}
+
+impl<'a, 'b, T: ?Sized, U: ?Sized> PartialOrd<QPath<'b, U>> for QPath<'a, T> {
+    fn partial_cmp(&self, other: &QPath<'b, U>) -> Option<cmp::Ordering> {
+        Some(self.path.cmp(&other.path))
+    }
+}
+
+impl<'a, T: ?Sized> Ord for QPath<'a, T> {
+    fn cmp(&self, other: &QPath<'a, T>) -> cmp::Ordering {
+        self.path.cmp(&other.path)
+    }
+}
+
+impl<'a, T: ?Sized> Hash for QPath<'a, T> {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.path.hash(h)
+    }
 }
 
 impl<'a, T: ?Sized + FSRead<'a>> AsRef<Path> for QPath<'a, T> {
@@ -71,43 +677,198 @@ impl<'a, T: ?Sized + FSRead<'a>> QPath<'a, T> {
         self.path.as_ref()
     }
 
+    // the path from the filesystem root, including any stripped prefix
+    fn full(&self) -> Cow<'_, Path> {
+        if self.root.is_empty() {
+            Cow::Borrowed(self.path.as_ref())
+        } else {
+            Cow::Owned(self.root.join(&self.path))
+        }
+    }
+
     pub fn open(&self) -> Result<T::ReadFile> {
-        self.parent.open(&self.path)
+        self.parent.open(self.full())
     }
 
     pub fn file_type(&self) -> Result<FileType> {
-        self.parent.file_type(&self.path)
+        self.parent.file_type(self.full())
     }
 
     pub fn exists(&self) -> bool {
-        self.parent.exists(&self.path)
+        self.parent.exists(self.full())
     }
 
     pub fn is_file(&self) -> bool {
-        self.parent.is_file(&self.path)
+        self.parent.is_file(self.full())
     }
 
     pub fn is_dir(&self) -> bool {
-        self.parent.is_dir(&self.path)
+        self.parent.is_dir(self.full())
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.parent.is_symlink(self.full())
     }
 
     pub fn read_dir(&self) -> Result<T::ReadDir> {
-        self.parent.read_dir(&self.path)
+        self.parent.read_dir(self.full())
+    }
+
+    /// Whether this path starts with `base`; see `Path::starts_with`.
+    pub fn starts_with<P: AsRef<Path>>(&self, base: P) -> bool {
+        self.path().starts_with(base)
+    }
+
+    /// Whether this path ends with `suffix`; see `Path::ends_with`.
+    pub fn ends_with<P: AsRef<Path>>(&self, suffix: P) -> bool {
+        self.path().ends_with(suffix)
+    }
+
+    /// Iterate over the lines of a file, like `BufRead::lines`.
+    ///
+    /// Line endings are removed. A line that isn't valid UTF-8 is an
+    /// `InvalidData` error in its place.
+    pub fn lines(&self) -> Result<impl Iterator<Item=Result<String>> + 'a> {
+        let path = self.full().into_owned();
+        let f = self.parent.open_buffered(&path)?;
+        Ok(f.lines().map(move |l| l.map_err(|e| Error::new("read", &path, e))))
+    }
+
+    /// The parent of this path, on the same filesystem.
+    pub fn parent(&self) -> Option<QPath<'a, T>> {
+        self.path.parent().map(|p| QPath { path: p.to_path_buf(), parent: self.parent, root: self.root.clone() })
+    }
+
+    /// Join a path onto this one, on the same filesystem.
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> QPath<'a, T> {
+        QPath { path: self.path.join(path), parent: self.parent, root: self.root.clone() }
+    }
+
+    /// Remove a prefix from this path, as `Path::strip_prefix` does.
+    ///
+    /// The result still refers to the same file, so it can be shown
+    /// relative to `base` and opened all the same. Its `parent` stops
+    /// at `base`.
+    pub fn strip_prefix<P: AsRef<Path>>(&self, base: P) -> Option<QPath<'a, T>> {
+        let rest = self.path.strip_prefix(&base)?.to_path_buf();
+        Some(QPath { path: rest, parent: self.parent, root: self.root.join(base) })
     }
 }
 
+impl<'a, T: ?Sized + FSRead<'a>> QPath<'a, T> where T::ReadFile: io::Seek {
+    /// Read exactly `len` bytes starting at `offset`.
+    ///
+    /// The file is seeked to `offset`, so only the bytes asked for are
+    /// read, and the backend's files have to support seeking. If the
+    /// file ends early, this is an `UnexpectedEof` error.
+    pub fn read_range(&self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let path = self.full();
+        let mut f = self.parent.open(&path)?;
+        let end = f.seek(io::SeekFrom::End(0)).map_err(|e| Error::new("read", &path, e))?;
+        if end < offset {
+            let err = io::Error::new(io::ErrorKind::UnexpectedEof, "Offset is past the end of the file.");
+            return Err(Error::new("read", &path, err));
+        }
+        f.seek(io::SeekFrom::Start(offset)).map_err(|e| Error::new("read", &path, e))?;
+        let mut buf = vec![0; len];
+        f.read_exact(&mut buf).map_err(|e| Error::new("read", &path, e))?;
+        Ok(buf)
+    }
+}
+
+/// A reader that can also seek, usable as `Box<dyn ReadSeek>`.
+///
+/// This is implemented for everything that is both `Read` and `Seek`.
+pub trait ReadSeek: io::Read + io::Seek {}
+
+impl<T: io::Read + io::Seek + ?Sized> ReadSeek for T {}
+
 /// Operations for readable file systems.
 pub trait FSRead<'a> : 'a{
     fn qualified<P: AsRef<Path>>(&'a self, path: P) -> QPath<'a, Self> {
-        QPath { path: path.as_ref().to_owned(), parent: self }
+        QPath { path: path.as_ref().to_owned(), parent: self, root: PathBuf::new() }
     }
 
     type ReadFile: io::Read;
     fn open<P: AsRef<Path>>(&self, path: P) -> Result<Self::ReadFile>;
 
-    // fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata>;
+    /// Open a file wrapped in a `BufReader`, for many small reads.
+    fn open_buffered<P: AsRef<Path>>(&self, path: P) -> Result<io::BufReader<Self::ReadFile>> {
+        self.open(path).map(io::BufReader::new)
+    }
+
+    /// Open a file as a boxed `ReadSeek`, for code that needs random
+    /// access but shouldn't be generic over the backend's file type.
+    ///
+    /// This is only available for backends whose files can seek.
+    fn open_read_seek<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn ReadSeek + 'static>>
+        where Self::ReadFile: io::Seek + 'static
+    {
+        self.open(path).map(|f| Box::new(f) as Box<dyn ReadSeek>)
+    }
+
+    /// Open a file, and get its metadata at the same time.
+    ///
+    /// Backends that can should read the metadata from the open file,
+    /// so both describe the same file even if it is replaced in
+    /// between. By default this calls `open` and then `metadata`.
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(Self::ReadFile, Metadata)> {
+        let f = self.open(&path)?;
+        let meta = self.metadata(path)?;
+        Ok((f, meta))
+    }
+
+    /// Get the size, type and modification time of a file or directory.
+    ///
+    /// By default this is unsupported, and returns an error.
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        let err = io::Error::new(io::ErrorKind::Unsupported, "Metadata not supported.");
+        Err(Error::new("stat", path, err))
+    }
+
     fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType>;
+
+    /// Look up the types of many paths at once.
+    ///
+    /// Results are in the same order as the paths. Backends that can
+    /// batch or pipeline lookups may override this; by default it
+    /// simply calls `file_type` for each path.
+    fn file_types<P: AsRef<Path>, I: IntoIterator<Item=P>>(&self, paths: I) -> Vec<Result<FileType>> {
+        paths.into_iter().map(|p| self.file_type(p)).collect()
+    }
+
+    /// Get the permissions of a file or directory.
+    ///
+    /// By default, anything that exists is reported as writable.
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.file_type(path).map(|_| Permissions::new(false))
+    }
     
+    /// Resolve "." and ".." components, and any symlinks the backend
+    /// knows about, into a canonical path from the filesystem root.
+    ///
+    /// The path must exist, and may not climb out of the root. By
+    /// default this only resolves "." and ".." lexically.
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        let mut parts = Vec::new();
+        for part in path.as_ref() {
+            match part.as_str() {
+                "." => {}
+                ".." => if parts.pop().is_none() {
+                    let err = io::Error::new(io::ErrorKind::PermissionDenied, "Path leaves the filesystem.");
+                    return Err(Error::new("canonicalize", path, err));
+                },
+                s => parts.push(s),
+            }
+        }
+        let canon = PathBuf::from(parts.join("/"));
+        if !self.exists(&canon) {
+            let err = io::Error::new(io::ErrorKind::NotFound, "File not found.");
+            return Err(Error::new("canonicalize", path, err));
+        }
+        Ok(canon)
+    }
+
     fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
         self.file_type(path).is_ok()
     }
@@ -120,22 +881,398 @@ pub trait FSRead<'a> : 'a{
         self.file_type(path).map(|t| t.is_dir()).unwrap_or(false)
     }
 
+    /// Whether `path` is itself a symlink, without following it.
+    ///
+    /// By default this is always false, for backends without symlinks.
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        let _ = path;
+        false
+    }
+
     type ReadDir: Iterator<Item=QPath<'a, Self>>;
     fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir>;
+
+    /// Read a whole directory at once, failing if any entry can't be
+    /// read.
+    ///
+    /// `read_dir` skips entries that fail, such as names that aren't
+    /// valid UTF-8. This instead returns the first such error, so the
+    /// listing is either complete or not given at all. By default
+    /// this collects `read_dir`, for backends where entries can't fail.
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.read_dir(path).map(|r| r.collect())
+    }
+
+    /// Iterate over everything under a directory, depth first.
+    ///
+    /// Each directory is yielded before its contents. Errors looking
+    /// up or listing an entry are yielded in its place, and the walk
+    /// carries on with the next entry.
+    fn walk<P: AsRef<Path>>(&'a self, path: P) -> Result<Walk<'a, Self>> {
+        self.read_dir(path).map(|r| Walk { stack: vec![r], pending: None, follow_links: true })
+    }
+
+    /// Set up a walk with depth limits, sorting or pruning; see
+    /// `WalkBuilder`.
+    ///
+    /// By default this walks the same entries as `walk`.
+    fn walk_builder<P: AsRef<Path>>(&'a self, path: P) -> WalkBuilder<'a, Self> {
+        WalkBuilder {
+            fs: self,
+            root: path.as_ref().to_path_buf(),
+            min_depth: 1,
+            max_depth: usize::MAX,
+            sort: None,
+            filter: None,
+        }
+    }
+
+    /// Iterate over every file under a directory, at any depth.
+    ///
+    /// This is `walk` without the directories, for callers that only
+    /// want files. Symlinks to files are listed, but symlinks to
+    /// directories are not descended into. Errors are yielded in place
+    /// of the entries they belong to, as with `walk`. The order is
+    /// unspecified.
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let mut walk = Walk::no_follow(self, prefix)?;
+        Ok(::std::iter::from_fn(move || walk.next_typed()).filter_map(|entry| {
+            // only symlinks still need their target looked up
+            let typed = entry.and_then(|(e, t)| match t {
+                Some(t) => Ok((e, t)),
+                None => e.file_type().map(|t| (e, t)),
+            });
+            match typed {
+                Ok((e, FileType::File)) => Some(Ok(e)),
+                Ok((_, FileType::Dir)) => None,
+                Err(e) => Some(Err(e)),
+            }
+        }))
+    }
+
+    /// The total size of all files under a path.
+    ///
+    /// Directories themselves count for nothing. A path to a file
+    /// gives the size of that file. Symlinks are not followed, and
+    /// count for nothing either.
+    fn disk_usage<P: AsRef<Path>>(&'a self, path: P) -> Result<u64> {
+        if self.is_symlink(&path) {
+            return Ok(0);
+        }
+        let meta = self.metadata(&path)?;
+        if !meta.is_dir() {
+            return Ok(meta.len());
+        }
+        let mut total = 0;
+        let mut walk = Walk::no_follow(self, path)?;
+        while let Some(entry) = walk.next_typed() {
+            if let (entry, Some(FileType::File)) = entry? {
+                total += self.metadata(entry.path())?.len();
+            }
+        }
+        Ok(total)
+    }
+
+    /// Take a snapshot of the layout of everything under `root`.
+    ///
+    /// Names and file types are read once, up front; see `Tree`.
+    fn tree<P: AsRef<Path>>(&'a self, root: P) -> Result<Tree> {
+        let root = root.as_ref();
+        let file_type = self.file_type(root)?;
+        let mut tree = Tree::new(file_type);
+        if file_type.is_dir() {
+            for entry in self.walk(root)? {
+                let entry = entry?;
+                tree.insert(entry.path().strip_prefix(root).unwrap_or(entry.path()), entry.file_type()?);
+            }
+        }
+        Ok(tree)
+    }
+
+    /// List everything a recursive remove of `path` would delete,
+    /// without deleting anything.
+    ///
+    /// The list is in the order a remove would go: everything in a
+    /// directory comes before the directory, and `path` itself is
+    /// last. A path to a file gives just that file. Symlinks are
+    /// listed themselves, and what they point to is not. Any error
+    /// reading the tree fails the whole plan, so it is never
+    /// incomplete.
+    fn plan_remove<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        let mut plan = Vec::new();
+        if !self.is_symlink(path) && self.file_type(path)?.is_dir() {
+            let mut walk = Walk::no_follow(self, path)?;
+            while let Some(entry) = walk.next_typed() {
+                plan.push(entry?.0.path().to_path_buf());
+            }
+            // walk yields each directory before its contents
+            plan.reverse();
+        }
+        plan.push(path.to_path_buf());
+        Ok(plan)
+    }
+
+    /// List every file under `root` with its size and SHA-256 digest.
+    ///
+    /// Paths are relative to `root`, and sorted. Files are hashed as
+    /// they are read, so they are never held in memory whole.
+    #[cfg(feature = "sha2")]
+    fn manifest<P: AsRef<Path>>(&'a self, root: P) -> Result<Vec<(PathBuf, u64, [u8; 32])>> {
+        let mut files = Vec::new();
+        for entry in self.walk(&root)? {
+            let entry = entry?;
+            if !entry.is_file() {
+                continue;
+            }
+            let mut hasher = Sha256::new();
+            let len = io::copy(&mut entry.open()?, &mut hasher).map_err(|e| Error::new("read", entry.path(), e))?;
+            let mut digest = [0; 32];
+            digest.copy_from_slice(&hasher.finalize());
+            let rel = entry.path().strip_prefix(&root).unwrap_or(entry.path()).to_path_buf();
+            files.push((rel, len, digest));
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Read a directory, keeping only entries whose file name matches
+    /// a regex.
+    #[cfg(feature = "regex")]
+    fn read_dir_matching<P: AsRef<Path>>(&'a self, path: P, re: &Regex) -> Result<Matching<Self::ReadDir>> {
+        self.read_dir(path).map(|r| DirEntriesExt::matching(r, re))
+    }
 }
 
 /// Operations for a writeable file system
 pub trait FSWrite<'a> : 'a {
-    /// The concrete type for writable files
+    /// The concrete type for writable files, which must implement
+    /// `io::Write`.
     type WriteFile: io::Write;
 
     /// This function will create a file if it does not exist, and will truncate it if it does.
     fn create<P: AsRef<Path>>(&self, path: P) -> Result<Self::WriteFile>;
 
+    /// Create a file wrapped in a `BufWriter`, for many small writes.
+    ///
+    /// Remember to flush the writer to see any errors from the final
+    /// write.
+    fn create_buffered<P: AsRef<Path>>(&self, path: P) -> Result<io::BufWriter<Self::WriteFile>> {
+        self.create(path).map(io::BufWriter::new)
+    }
+
+    /// Create a file as a boxed `io::Write`.
+    ///
+    /// This is for code such as serializers that would rather not be
+    /// generic over the backend's file type.
+    fn create_writer<P: AsRef<Path>>(&self, path: P) -> Result<Box<dyn io::Write + 'static>> where Self::WriteFile: 'static {
+        self.create(path).map(|f| Box::new(f) as Box<dyn io::Write>)
+    }
+
     /// This function will append to an already existing file.
     ///
     /// If the file doesn't exist, an error is returned.
     fn append<P: AsRef<Path>>(&self, path: P) -> Result<Self::WriteFile>;
 
+    /// Open a file for writing with the given options.
+    ///
+    /// By default this is built from `create`, `append` and
+    /// `truncate`, so it supports appending and truncating but not
+    /// `create_new` or writing over a file in place. Those return an
+    /// `Unsupported` error.
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<Self::WriteFile> {
+        opts.check(&path)?;
+        let unsupported = |msg| Err(Error::new("open", &path, io::Error::new(io::ErrorKind::Unsupported, msg)));
+        if opts.create_new {
+            unsupported("Exclusive create not supported.")
+        } else if opts.append {
+            match self.append(&path) {
+                Err(ref e) if opts.create && e.kind() == io::ErrorKind::NotFound => self.create(&path),
+                r => r,
+            }
+        } else if opts.truncate {
+            if !opts.create {
+                // fails if the file is missing
+                self.truncate(&path, 0)?;
+            }
+            self.create(&path)
+        } else {
+            unsupported("Writing in place not supported.")
+        }
+    }
 
+    /// Set the length of an existing file.
+    ///
+    /// Shrinking the file discards everything past `len`, and growing
+    /// it fills the new space with zeros.
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()>;
+
+    /// Set the permissions of a file or directory.
+    ///
+    /// By default this is unsupported, and returns an error.
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        let _ = perm;
+        let err = io::Error::new(io::ErrorKind::Unsupported, "Permissions not supported.");
+        Err(Error::new("set permissions on", path, err))
+    }
+
+    /// Create a hard link at `link` pointing to the file at `existing`.
+    ///
+    /// Both paths must be on the same underlying filesystem. By
+    /// default this is unsupported, and returns an error.
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        let _ = link;
+        let err = io::Error::new(io::ErrorKind::Unsupported, "Hard links not supported.");
+        Err(Error::new("link", existing, err))
+    }
+
+    /// Move a file or directory from `from` to `to`.
+    ///
+    /// The parent of `to` must already exist. By default this is
+    /// unsupported, and returns an error.
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        let _ = to;
+        let err = io::Error::new(io::ErrorKind::Unsupported, "Rename not supported.");
+        Err(Error::new("rename", from, err))
+    }
+
+    /// Like `rename`, but first create any missing parents of `to`.
+    fn rename_create<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        if let Some(parent) = to.as_ref().parent() {
+            self.create_dir_all(parent)?;
+        }
+        self.rename(from, to)
+    }
+
+    /// Remove a file.
+    ///
+    /// By default this is unsupported, and returns an error.
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let err = io::Error::new(io::ErrorKind::Unsupported, "Removal not supported.");
+        Err(Error::new("remove", path, err))
+    }
+
+    /// Remove an empty directory.
+    ///
+    /// By default this is unsupported, and returns an error.
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let err = io::Error::new(io::ErrorKind::Unsupported, "Removal not supported.");
+        Err(Error::new("remove", path, err))
+    }
+
+    /// Create a new, empty directory.
+    ///
+    /// The parent directory must already exist.
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+
+    /// Create a directory and all of its missing parents.
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut current = PathBuf::new();
+        for part in path.as_ref() {
+            current.push(part);
+            match self.create_dir(&current) {
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the contents of one file to another, returning the number
+    /// of bytes copied.
+    ///
+    /// The destination is created if it does not exist, and truncated
+    /// if it does.
+    fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<u64> where Self: FSRead<'a> {
+        let mut src = self.open(&from)?;
+        let mut dst = self.create(to)?;
+        io::copy(&mut src, &mut dst).map_err(|e| Error::new("copy", from, e))
+    }
+
+    /// Copy a directory tree, copying files on up to `threads` threads
+    /// at once.
+    ///
+    /// The tree is walked and its directories created first, and then
+    /// the files are shared out between the threads. The first error
+    /// stops any further copies from starting. A `threads` of 0 is
+    /// treated as 1. Symlinks are not followed: a link to a file is
+    /// copied as a file, and a link to a directory is skipped.
+    fn copy_tree_parallel<P: AsRef<Path>, Q: AsRef<Path>>(&'a self, from: P, to: Q, threads: usize) -> Result<()>
+        where Self: FSRead<'a> + Sync
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        if !self.file_type(from)?.is_dir() {
+            return self.copy(from, to).map(|_| ());
+        }
+        self.create_dir_all(to)?;
+        let mut files = Vec::new();
+        let mut walk = Walk::no_follow(self, from)?;
+        while let Some(entry) = walk.next_typed() {
+            let (entry, file_type) = entry?;
+            let dest = to.join(entry.path().strip_prefix(from).unwrap_or(entry.path()));
+            match file_type {
+                Some(FileType::Dir) => self.create_dir_all(&dest)?,
+                Some(FileType::File) => files.push((entry.path().to_path_buf(), dest)),
+                None => if entry.is_file() {
+                    files.push((entry.path().to_path_buf(), dest));
+                },
+            }
+        }
+
+        let files = Mutex::new(files.into_iter());
+        let failed = Mutex::new(None);
+        thread::scope(|scope| {
+            for _ in 0..cmp::max(threads, 1) {
+                scope.spawn(|| {
+                    while failed.lock().unwrap().is_none() {
+                        let next = files.lock().unwrap().next();
+                        let (src, dst) = match next {
+                            Some(f) => f,
+                            None => break,
+                        };
+                        if let Err(e) = self.copy(src, dst) {
+                            failed.lock().unwrap().get_or_insert(e);
+                        }
+                    }
+                });
+            }
+        });
+        match failed.into_inner().unwrap() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+}
+
+/// Copy a file from one filesystem to another, returning the number
+/// of bytes copied.
+pub fn copy_between<'a, S, D, P, Q>(src: &'a S, src_path: P, dst: &'a D, dst_path: Q) -> Result<u64>
+    where S: FSRead<'a>, D: FSWrite<'a>, P: AsRef<Path>, Q: AsRef<Path>
+{
+    let mut from = src.open(&src_path)?;
+    let mut to = dst.create(dst_path)?;
+    io::copy(&mut from, &mut to).map_err(|e| Error::new("copy", src_path, e))
+}
+
+/// Copy a file or a whole directory tree from one filesystem to
+/// another, returning the total number of bytes copied.
+///
+/// Directories are created on the destination as needed.
+pub fn copy_tree_between<'a, S, D, P, Q>(src: &'a S, src_path: P, dst: &'a D, dst_path: Q) -> Result<u64>
+    where S: FSRead<'a>, D: FSWrite<'a>, P: AsRef<Path>, Q: AsRef<Path>
+{
+    if !src.file_type(&src_path)?.is_dir() {
+        return copy_between(src, src_path, dst, dst_path);
+    }
+    dst.create_dir_all(&dst_path)?;
+    let mut total = 0;
+    for entry in src.read_dir(&src_path)? {
+        if let Some(name) = entry.path().file_name() {
+            total += copy_tree_between(src, entry.path(), dst, dst_path.as_ref().join(name))?;
+        }
+    }
+    Ok(total)
 }
@@ -0,0 +1,122 @@
+use std::{io, iter};
+use reqwest::{Method, StatusCode, Url};
+use reqwest::blocking::{Client, Response};
+use reqwest::header::CONTENT_LENGTH;
+use path::Path;
+use fs::{FSRead, Error, Result, FileType, Metadata, QPath};
+
+/// A read-only filesystem of files served over plain HTTP.
+///
+/// Paths map onto URL path segments under a base URL. Files are read
+/// with GET, streaming the response body, and looked up with HEAD.
+/// HTTP has no notion of directories, so everything that exists is a
+/// file and `read_dir` always fails.
+pub struct HttpFS {
+    base: Url,
+    client: Client,
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+// turn a failed request or an unsuccessful status into an error
+fn check<P: AsRef<Path>>(op: &'static str, path: P, resp: reqwest::Result<Response>) -> Result<Response> {
+    let resp = resp.map_err(|e| Error::new(op, &path, io::Error::new(io::ErrorKind::Other, e)))?;
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let kind = match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => io::ErrorKind::NotFound,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => io::ErrorKind::PermissionDenied,
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => io::ErrorKind::TimedOut,
+        _ => io::ErrorKind::Other,
+    };
+    Err(error(op, path, kind, status.canonical_reason().unwrap_or("Request failed.")))
+}
+
+impl HttpFS {
+    /// Read files from under `base`.
+    pub fn new(base: &str) -> Result<HttpFS> {
+        HttpFS::with_client(base, Client::new())
+    }
+
+    /// Like `new`, but use a preconfigured client, for example to set
+    /// timeouts or authentication headers.
+    pub fn with_client(base: &str, client: Client) -> Result<HttpFS> {
+        let invalid = |msg: &str| Error::from(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        let mut base = Url::parse(base).map_err(|_| invalid("Invalid base URL."))?;
+        if base.cannot_be_a_base() {
+            return Err(invalid("Invalid base URL."));
+        }
+        base.path_segments_mut().unwrap().pop_if_empty();
+        Ok(HttpFS { base: base, client: client })
+    }
+
+    fn url<P: AsRef<Path>>(&self, path: P) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut().unwrap().extend(path.as_ref());
+        url
+    }
+
+    fn head<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<Response> {
+        check(op, &path, self.client.request(Method::HEAD, self.url(&path)).send())
+    }
+}
+
+impl<'a> FSRead<'a> for HttpFS {
+    type ReadFile = Response;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<Response> {
+        check("open", &path, self.client.request(Method::GET, self.url(&path)).send())
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.head("stat", path).map(|_| FileType::File)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        let resp = self.head("stat", path)?;
+        let len = resp.headers().get(CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse().ok());
+        Ok(Metadata::new(FileType::File, len.unwrap_or(0), None))
+    }
+
+    type ReadDir = iter::Empty<QPath<'a, HttpFS>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        Err(error("read directory", path, io::ErrorKind::Unsupported, "Listing not supported."))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::FSRead;
+    use mockito::Server;
+    use std::io::Read;
+
+    #[test]
+    fn http() {
+        let mut server = Server::new();
+        let get = server.mock("GET", "/assets/a%20b.txt").with_body("hello").create();
+        let head = server.mock("HEAD", "/assets/a%20b.txt").with_header("content-length", "5").expect(2).create();
+        let missing = server.mock("GET", "/assets/missing").with_status(404).create();
+        let missing_head = server.mock("HEAD", "/assets/missing").with_status(404).create();
+
+        let h = HttpFS::new(&format!("{}/assets/", server.url())).unwrap();
+        let mut s = String::new();
+        h.open("a b.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+        assert!(h.is_file("/a b.txt"));
+        assert_eq!(h.metadata("a b.txt").unwrap().len(), 5);
+        assert_eq!(h.open("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(!h.exists("missing"));
+        assert_eq!(h.read_dir("").unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+        get.assert();
+        head.assert();
+        missing.assert();
+        missing_head.assert();
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+use path::{Path, PathBuf};
+
+/// A store of shared path components.
+///
+/// Walking a large tree gives many paths that repeat the same
+/// directory names. Interning them keeps one copy of each distinct
+/// component, with each `InternedPath` holding only pointers.
+#[derive(Default)]
+pub struct Interner {
+    parts: HashSet<Rc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    /// Get the shared copy of a component, adding it if it is new.
+    pub fn intern(&mut self, part: &str) -> Rc<str> {
+        if let Some(p) = self.parts.get(part) {
+            return p.clone();
+        }
+        let p: Rc<str> = part.into();
+        self.parts.insert(p.clone());
+        p
+    }
+
+    /// Intern every component of a path.
+    pub fn path<P: AsRef<Path>>(&mut self, path: P) -> InternedPath {
+        InternedPath { parts: path.as_ref().iter().map(|p| self.intern(p)).collect() }
+    }
+
+    /// The number of distinct components stored.
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
+
+/// A path made of interned components, from `Interner::path`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Default)]
+pub struct InternedPath {
+    parts: Vec<Rc<str>>,
+}
+
+impl InternedPath {
+    /// The components of this path.
+    pub fn parts(&self) -> &[Rc<str>] {
+        &self.parts
+    }
+
+    pub fn to_path_buf(&self) -> PathBuf {
+        let mut p = PathBuf::new();
+        for part in &self.parts {
+            p.push(&**part);
+        }
+        p
+    }
+}
+
+impl<'a> From<&'a InternedPath> for PathBuf {
+    fn from(p: &'a InternedPath) -> PathBuf {
+        p.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interner_shares() {
+        let mut i = Interner::new();
+        let a = i.path("assets/tiles/0/1.png");
+        let b = i.path("/assets/tiles/0/2.png/");
+        assert_eq!(i.len(), 5);
+        for n in 0..3 {
+            assert!(Rc::ptr_eq(&a.parts()[n], &b.parts()[n]));
+        }
+        assert!(!Rc::ptr_eq(&a.parts()[3], &b.parts()[3]));
+        assert_eq!(a.to_path_buf().as_str(), "assets/tiles/0/1.png");
+        assert_eq!(PathBuf::from(&b), "assets/tiles/0/2.png");
+        assert_eq!(i.path(""), InternedPath::default());
+    }
+}
@@ -0,0 +1,409 @@
+use std::{io, vec};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Metadata, OpenOptions, QPath};
+
+/// A flat key-value store that `KvFS` keeps its files in.
+///
+/// This is the small subset of an embedded database like sled or
+/// RocksDB that a filesystem needs. Methods take `&self`, so stores
+/// handle their own locking.
+pub trait KvStore {
+    /// The value stored under `key`, if any.
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>>;
+
+    /// Store `value` under `key`, replacing any old value.
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()>;
+
+    /// Remove `key`. Removing a missing key is not an error.
+    fn delete(&self, key: &[u8]) -> io::Result<()>;
+
+    /// Every key starting with `prefix`, in any order.
+    fn scan_prefix(&self, prefix: &[u8]) -> io::Result<Vec<Vec<u8>>>;
+}
+
+/// An in-memory store, mostly useful for testing.
+impl KvStore for Mutex<BTreeMap<Vec<u8>, Vec<u8>>> {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.lock().unwrap().get(key).cloned())
+    }
+
+    fn put(&self, key: &[u8], value: &[u8]) -> io::Result<()> {
+        self.lock().unwrap().insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, key: &[u8]) -> io::Result<()> {
+        self.lock().unwrap().remove(key);
+        Ok(())
+    }
+
+    fn scan_prefix(&self, prefix: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+        let map = self.lock().unwrap();
+        Ok(map.range(prefix.to_vec()..).map(|(k, _)| k).take_while(|k| k.starts_with(prefix)).cloned().collect())
+    }
+}
+
+// each value starts with a tag byte saying what it is
+const DIR: u8 = b'd';
+const FILE: u8 = b'f';
+
+/// A filesystem kept in a key-value store.
+///
+/// Each file and directory is one entry, keyed by its components
+/// joined by "/", so listing a directory is a prefix scan. Like
+/// `MemFS`, files can only be created inside directories that already
+/// exist. Files written through `KvFS` are buffered, and stored when
+/// flushed or dropped.
+pub struct KvFS<S> {
+    store: Arc<S>,
+}
+
+enum Node {
+    Dir,
+    File(Vec<u8>),
+}
+
+fn key<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref().iter().collect::<Vec<_>>().join("/")
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+impl<S: KvStore> KvFS<S> {
+    pub fn new(store: S) -> KvFS<S> {
+        KvFS { store: Arc::new(store) }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.store
+    }
+
+    fn node<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<Option<Node>> {
+        let k = key(&path);
+        if k.is_empty() {
+            return Ok(Some(Node::Dir));
+        }
+        match self.store.get(k.as_bytes()).map_err(|e| Error::new(op, &path, e))? {
+            Some(ref v) if v.first() == Some(&DIR) => Ok(Some(Node::Dir)),
+            Some(ref v) if v.first() == Some(&FILE) => Ok(Some(Node::File(v[1..].to_vec()))),
+            Some(_) => Err(error(op, path, io::ErrorKind::InvalidData, "Unrecognized entry.")),
+            None => Ok(None),
+        }
+    }
+
+    // make sure the parent of path is an existing directory
+    fn check_parent<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<()> {
+        let parent = path.as_ref().parent().unwrap_or(Path::new(""));
+        match self.node(op, parent)? {
+            Some(Node::Dir) => Ok(()),
+            Some(Node::File(_)) => Err(error(op, path, io::ErrorKind::Other, "Not a directory.")),
+            None => Err(error(op, path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    // the keys of everything under a directory
+    fn descendants<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<Vec<String>> {
+        let dir = key(&path);
+        let prefix = if dir.is_empty() { dir } else { dir + "/" };
+        let keys = self.store.scan_prefix(prefix.as_bytes()).map_err(|e| Error::new(op, &path, e))?;
+        Ok(keys.into_iter().filter_map(|k| String::from_utf8(k).ok()).collect())
+    }
+
+    fn put<P: AsRef<Path>>(&self, op: &'static str, path: P, tag: u8, data: &[u8]) -> Result<()> {
+        let mut value = Vec::with_capacity(data.len() + 1);
+        value.push(tag);
+        value.extend_from_slice(data);
+        self.store.put(key(&path).as_bytes(), &value).map_err(|e| Error::new(op, path, e))
+    }
+
+    fn file<P: AsRef<Path>>(&self, op: &'static str, path: P, data: Vec<u8>) -> Result<KvFile<S>> {
+        let f = KvFile { store: self.store.clone(), path: path.as_ref().to_path_buf(), data: data, dirty: true };
+        f.store(op)?;
+        Ok(f)
+    }
+}
+
+/// A writable handle to a file in a `KvFS`.
+pub struct KvFile<S: KvStore> {
+    store: Arc<S>,
+    path: PathBuf,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+impl<S: KvStore> KvFile<S> {
+    /// Store the contents, reporting any error.
+    pub fn finish(mut self) -> Result<()> {
+        self.store("write")?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn store(&self, op: &'static str) -> Result<()> {
+        let mut value = Vec::with_capacity(self.data.len() + 1);
+        value.push(FILE);
+        value.extend_from_slice(&self.data);
+        self.store.put(key(&self.path).as_bytes(), &value).map_err(|e| Error::new(op, &self.path, e))
+    }
+}
+
+impl<S: KvStore> io::Write for KvFile<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        self.dirty = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.dirty {
+            self.store("write")?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl<S: KvStore> Drop for KvFile<S> {
+    fn drop(&mut self) {
+        if self.dirty {
+            let _ = self.store("write");
+        }
+    }
+}
+
+pub struct ReadDir<'a, S: 'a> {
+    iter: vec::IntoIter<PathBuf>,
+    parent: &'a KvFS<S>,
+}
+
+impl<'a, S: KvStore + 'a> Iterator for ReadDir<'a, S> {
+    type Item = QPath<'a, KvFS<S>>;
+
+    fn next(&mut self) -> Option<QPath<'a, KvFS<S>>> {
+        self.iter.next().map(|p| self.parent.qualified(p))
+    }
+}
+
+impl<'a, S: KvStore + 'a> FSRead<'a> for KvFS<S> {
+    type ReadFile = io::Cursor<Vec<u8>>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Cursor<Vec<u8>>> {
+        match self.node("open", &path)? {
+            Some(Node::File(data)) => Ok(io::Cursor::new(data)),
+            Some(Node::Dir) => Err(error("open", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("open", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        match self.node("stat", &path)? {
+            Some(Node::File(_)) => Ok(FileType::File),
+            Some(Node::Dir) => Ok(FileType::Dir),
+            None => Err(error("stat", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        match self.node("stat", &path)? {
+            Some(Node::File(data)) => Ok(Metadata::new(FileType::File, data.len() as u64, None)),
+            Some(Node::Dir) => Ok(Metadata::new(FileType::Dir, 0, None)),
+            None => Err(error("stat", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    type ReadDir = ReadDir<'a, S>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a, S>> {
+        match self.node("read directory", &path)? {
+            Some(Node::Dir) => {}
+            Some(Node::File(_)) => return Err(error("read directory", path, io::ErrorKind::Other, "Not a directory.")),
+            None => return Err(error("read directory", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+        let skip = if path.as_ref().is_empty() { 0 } else { key(&path).len() + 1 };
+        let mut children: Vec<PathBuf> = self.descendants("read directory", &path)?.into_iter()
+            .filter(|k| !k[skip..].contains('/'))
+            .map(PathBuf::from)
+            .collect();
+        children.sort();
+        Ok(ReadDir { iter: children.into_iter(), parent: self })
+    }
+}
+
+impl<'a, S: KvStore + 'a> FSWrite<'a> for KvFS<S> {
+    type WriteFile = KvFile<S>;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<KvFile<S>> {
+        self.check_parent("create", &path)?;
+        match self.node("create", &path)? {
+            _ if path.as_ref().is_empty() => Err(error("create", path, io::ErrorKind::Other, "Is a directory.")),
+            Some(Node::Dir) => Err(error("create", path, io::ErrorKind::Other, "Is a directory.")),
+            _ => self.file("create", path, Vec::new()),
+        }
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<KvFile<S>> {
+        match self.node("append to", &path)? {
+            Some(Node::File(data)) => self.file("append to", path, data),
+            Some(Node::Dir) => Err(error("append to", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("append to", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<KvFile<S>> {
+        opts.check(&path)?;
+        let exists = self.node("open", &path)?.is_some();
+        if opts.create_new && exists {
+            return Err(error("open", path, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        if !exists && !opts.create && !opts.create_new {
+            return Err(error("open", path, io::ErrorKind::NotFound, "File not found."));
+        }
+        if opts.append && exists {
+            self.append(path)
+        } else if opts.append || opts.truncate || !exists {
+            self.create(path)
+        } else {
+            // files here can only be written from the end
+            Err(error("open", path, io::ErrorKind::Unsupported, "Writing in place not supported."))
+        }
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        match self.node("truncate", &path)? {
+            Some(Node::File(mut data)) => {
+                data.resize(len as usize, 0);
+                self.put("truncate", path, FILE, &data)
+            }
+            Some(Node::Dir) => Err(error("truncate", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("truncate", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        let node = match self.node("rename", &from)? {
+            Some(_) if from.as_ref().is_empty() => {
+                return Err(error("rename", from, io::ErrorKind::PermissionDenied, "Cannot move the root."));
+            }
+            Some(node) => node,
+            None => return Err(error("rename", from, io::ErrorKind::NotFound, "File not found.")),
+        };
+        self.check_parent("rename", &to)?;
+        let (old, new) = (key(&from), key(&to));
+        if new == old {
+            return Ok(());
+        }
+        if new.starts_with(&(old.clone() + "/")) {
+            return Err(error("rename", from, io::ErrorKind::InvalidInput, "Cannot move a directory into itself."));
+        }
+        match (self.node("rename", &to)?, &node) {
+            (Some(Node::Dir), _) | (Some(Node::File(_)), &Node::Dir) => {
+                return Err(error("rename", to, io::ErrorKind::AlreadyExists, "File exists."));
+            }
+            _ => {}
+        }
+        // move everything underneath, then the entry itself
+        let children = match node {
+            Node::Dir => self.descendants("rename", &from)?,
+            Node::File(_) => Vec::new(),
+        };
+        let err = |e| Error::new("rename", &from, e);
+        for k in children.iter().map(String::as_str).chain(Some(old.as_str())) {
+            let value = self.store.get(k.as_bytes()).map_err(err)?;
+            if let Some(value) = value {
+                let moved = format!("{}{}", new, &k[old.len()..]);
+                self.store.put(moved.as_bytes(), &value).map_err(err)?;
+                self.store.delete(k.as_bytes()).map_err(err)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self.node("remove", &path)? {
+            Some(Node::File(_)) => self.store.delete(key(&path).as_bytes()).map_err(|e| Error::new("remove", path, e)),
+            Some(Node::Dir) => Err(error("remove", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("remove", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self.node("remove", &path)? {
+            Some(Node::Dir) if path.as_ref().is_empty() => {
+                Err(error("remove", path, io::ErrorKind::PermissionDenied, "Cannot remove the root."))
+            }
+            Some(Node::Dir) => {
+                if !self.descendants("remove", &path)?.is_empty() {
+                    return Err(error("remove", path, io::ErrorKind::Other, "Directory not empty."));
+                }
+                self.store.delete(key(&path).as_bytes()).map_err(|e| Error::new("remove", path, e))
+            }
+            Some(Node::File(_)) => Err(error("remove", path, io::ErrorKind::Other, "Not a directory.")),
+            None => Err(error("remove", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.check_parent("create directory", &path)?;
+        if self.node("create directory", &path)?.is_some() {
+            return Err(error("create directory", path, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        self.put("create directory", path, DIR, &[])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite};
+    use std::io::{Read, Write};
+
+    fn kv() -> KvFS<Mutex<BTreeMap<Vec<u8>, Vec<u8>>>> {
+        KvFS::new(Mutex::new(BTreeMap::new()))
+    }
+
+    fn names<'a, T: FSRead<'a>>(fs: &'a T, path: &str) -> Vec<PathBuf> {
+        fs.read_dir(path).unwrap().map(|p| p.path().to_path_buf()).collect()
+    }
+
+    #[test]
+    fn kv_readwrite() {
+        let k = kv();
+        k.create_dir("tiles").unwrap();
+        k.create_dir("tiles/0").unwrap();
+        k.create("tiles/0/1.png").unwrap().write_all(b"png").unwrap();
+        k.create("tiles/0/2.png").unwrap().write_all(b"png").unwrap();
+        k.create("index.html").unwrap().write_all(b"<html>").unwrap();
+        k.append("index.html").unwrap().write_all(b"</html>").unwrap();
+
+        let mut s = String::new();
+        k.open("/index.html").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "<html></html>");
+        assert_eq!(names(&k, ""), vec!["index.html", "tiles"]);
+        assert_eq!(names(&k, "tiles"), vec!["tiles/0"]);
+        assert_eq!(names(&k, "tiles/0"), vec!["tiles/0/1.png", "tiles/0/2.png"]);
+        assert!(k.is_dir("tiles/0") && k.is_file("tiles/0/1.png"));
+        assert_eq!(k.metadata("index.html").unwrap().len(), 13);
+        assert_eq!(k.get_ref().lock().unwrap().len(), 5);
+
+        assert_eq!(k.create("missing/a").err().unwrap().kind(), io::ErrorKind::NotFound);
+        assert_eq!(k.remove_dir("tiles/0").unwrap_err().to_string(), "failed to remove tiles/0: Directory not empty.");
+    }
+
+    #[test]
+    fn kv_rename_remove() {
+        let k = kv();
+        k.create_dir("a").unwrap();
+        k.create_dir("a/b").unwrap();
+        k.create("a/b/c").unwrap().write_all(b"c").unwrap();
+        k.rename("a", "z").unwrap();
+        assert!(!k.exists("a") && !k.exists("a/b/c"));
+        assert_eq!(names(&k, "z/b"), vec!["z/b/c"]);
+        k.remove_file("z/b/c").unwrap();
+        k.remove_dir("z/b").unwrap();
+        assert!(names(&k, "z").is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use std::io;
+use std::io::Write;
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Mutex;
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType};
+
+// where the current version of a path lives
+pub enum Layer {
+    Upper,
+    Base,
+    Hidden,
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+/// A writable upper layer over a read-only base, as used by
+/// `CopyOnWrite` and `DryRun`.
+///
+/// This keeps track of what was removed from the base, and does the
+/// copying up and the checks for writes. The upper layer is passed in
+/// to each call, since the two wrappers hold it differently.
+pub struct Layers<'a, B: 'a + ?Sized> {
+    pub base: &'a B,
+    // paths whose base version, and everything under it, is gone
+    hidden: Mutex<HashSet<PathBuf>>,
+}
+
+impl<'a, B: ?Sized> Layers<'a, B> {
+    pub fn new(base: &'a B) -> Layers<'a, B> {
+        Layers { base: base, hidden: Mutex::new(HashSet::new()) }
+    }
+
+    fn hide<P: AsRef<Path>>(&self, path: P) {
+        self.hidden.lock().unwrap().insert(path.as_ref().to_path_buf());
+    }
+}
+
+impl<'a, B: ?Sized + FSRead<'a>> Layers<'a, B> {
+    pub fn layer<'u, U: ?Sized + FSRead<'u>, P: AsRef<Path>>(&self, upper: &'u U, path: P) -> Layer {
+        let path = path.as_ref();
+        if upper.exists(path) {
+            return Layer::Upper;
+        }
+        let hidden = self.hidden.lock().unwrap();
+        let mut p = Some(path);
+        while let Some(q) = p {
+            if hidden.contains(q) {
+                return Layer::Hidden;
+            }
+            p = q.parent();
+        }
+        Layer::Base
+    }
+
+    pub fn file_type<'u, U: ?Sized + FSRead<'u>, P: AsRef<Path>>(&self, upper: &'u U, path: P) -> Result<FileType> {
+        match self.layer(upper, &path) {
+            Layer::Upper => upper.file_type(path),
+            Layer::Base => self.base.file_type(path),
+            Layer::Hidden => Err(error("stat", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn exists<'u, U: ?Sized + FSRead<'u>, P: AsRef<Path>>(&self, upper: &'u U, path: P) -> bool {
+        self.file_type(upper, path).is_ok()
+    }
+
+    fn is_dir<'u, U: ?Sized + FSRead<'u>, P: AsRef<Path>>(&self, upper: &'u U, path: P) -> bool {
+        self.file_type(upper, path).map(|t| t.is_dir()).unwrap_or(false)
+    }
+
+    // the visible entries of a directory, from both layers
+    pub fn children<'u, U: ?Sized + FSRead<'u>, P: AsRef<Path>>(&self, upper: &'u U, path: P) -> Result<Vec<PathBuf>> {
+        let path = path.as_ref();
+        if !self.file_type(upper, path)?.is_dir() {
+            return Err(error("read directory", path, io::ErrorKind::Other, "Not a directory."));
+        }
+        // entries are keyed by components, so both layers agree on names
+        let mut names = BTreeSet::new();
+        if upper.is_dir(path) {
+            for entry in upper.read_dir(path)? {
+                names.insert(entry.path().iter().collect::<Vec<_>>().join("/"));
+            }
+        }
+        if self.base.is_dir(path) {
+            for entry in self.base.read_dir(path)? {
+                match self.layer(upper, entry.path()) {
+                    Layer::Hidden => {}
+                    _ => { names.insert(entry.path().iter().collect::<Vec<_>>().join("/")); }
+                }
+            }
+        }
+        Ok(names.into_iter().map(PathBuf::from).collect())
+    }
+
+    // make sure the parent of path is a directory, and mirror it in
+    // the upper layer so path can be written there
+    pub fn prepare_parent<'u, U, P>(&self, upper: &'u U, op: &'static str, path: P) -> Result<()>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>
+    {
+        let parent = path.as_ref().parent().unwrap_or(Path::new(""));
+        match self.file_type(upper, parent) {
+            Ok(FileType::Dir) => upper.create_dir_all(parent),
+            Ok(FileType::File) => Err(error(op, path, io::ErrorKind::Other, "Not a directory.")),
+            Err(_) => Err(error(op, path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    // copy a path, and everything under it, from the base into the upper
+    pub fn copy_up<'u, U, P>(&self, upper: &'u U, op: &'static str, path: P) -> Result<()>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        match self.layer(upper, path) {
+            Layer::Hidden => return Err(error(op, path, io::ErrorKind::NotFound, "File not found.")),
+            // a directory mirrored for a new file may still have
+            // children that are only in the base
+            Layer::Upper if !upper.is_dir(path) || !self.base.is_dir(path) => return Ok(()),
+            Layer::Upper => {}
+            Layer::Base => if self.base.file_type(path)? == FileType::File {
+                self.prepare_parent(upper, op, path)?;
+                let mut src = self.base.open(path)?;
+                let mut dst = upper.create(path)?;
+                return io::copy(&mut src, &mut dst).and_then(|_| dst.flush()).map_err(|e| Error::new("copy", path, e));
+            },
+        }
+        upper.create_dir_all(path)?;
+        for child in self.children(upper, path)? {
+            self.copy_up(upper, op, child)?;
+        }
+        Ok(())
+    }
+
+    pub fn create<'u, U, P>(&self, upper: &'u U, path: P) -> Result<U::WriteFile>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>
+    {
+        self.prepare_parent(upper, "create", &path)?;
+        if self.is_dir(upper, &path) {
+            return Err(error("create", path, io::ErrorKind::Other, "Is a directory."));
+        }
+        upper.create(path)
+    }
+
+    pub fn hard_link<'u, U, P, Q>(&self, upper: &'u U, existing: P, link: Q) -> Result<()>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>, Q: AsRef<Path>
+    {
+        self.copy_up(upper, "link", &existing)?;
+        self.prepare_parent(upper, "link", &link)?;
+        if self.exists(upper, &link) {
+            return Err(error("link", link, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        upper.hard_link(existing, link)
+    }
+
+    pub fn rename<'u, U, P, Q>(&self, upper: &'u U, from: P, to: Q) -> Result<()>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>, Q: AsRef<Path>
+    {
+        if from.as_ref().is_empty() {
+            return Err(error("rename", from, io::ErrorKind::PermissionDenied, "Cannot move the root."));
+        }
+        self.copy_up(upper, "rename", &from)?;
+        self.prepare_parent(upper, "rename", &to)?;
+        if self.is_dir(upper, &to) && !upper.is_dir(&to) {
+            return Err(error("rename", to, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        upper.rename(&from, to)?;
+        self.hide(from);
+        Ok(())
+    }
+
+    pub fn remove_file<'u, U, P>(&self, upper: &'u U, path: P) -> Result<()>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>
+    {
+        match self.file_type(upper, &path) {
+            Ok(FileType::File) => {}
+            Ok(FileType::Dir) => return Err(error("remove", path, io::ErrorKind::Other, "Is a directory.")),
+            Err(_) => return Err(error("remove", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+        if upper.exists(&path) {
+            upper.remove_file(&path)?;
+        }
+        self.hide(path);
+        Ok(())
+    }
+
+    pub fn remove_dir<'u, U, P>(&self, upper: &'u U, path: P) -> Result<()>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>
+    {
+        if path.as_ref().is_empty() {
+            return Err(error("remove", path, io::ErrorKind::PermissionDenied, "Cannot remove the root."));
+        }
+        match self.file_type(upper, &path) {
+            Ok(FileType::Dir) => {}
+            Ok(FileType::File) => return Err(error("remove", path, io::ErrorKind::Other, "Not a directory.")),
+            Err(_) => return Err(error("remove", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+        if !self.children(upper, &path)?.is_empty() {
+            return Err(error("remove", path, io::ErrorKind::Other, "Directory not empty."));
+        }
+        if upper.exists(&path) {
+            upper.remove_dir(&path)?;
+        }
+        self.hide(path);
+        Ok(())
+    }
+
+    pub fn create_dir<'u, U, P>(&self, upper: &'u U, path: P) -> Result<()>
+        where U: ?Sized + FSRead<'u> + FSWrite<'u>, P: AsRef<Path>
+    {
+        self.prepare_parent(upper, "create directory", &path)?;
+        if self.exists(upper, &path) {
+            return Err(error("create directory", path, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        upper.create_dir(path)
+    }
+}
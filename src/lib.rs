@@ -1,9 +1,145 @@
 #![feature(path_relative_from)]
 
+#[cfg(test)]
+extern crate serde_json;
+#[cfg(test)]
+extern crate tempdir;
+#[cfg(feature = "aws-sdk-s3")]
+extern crate aws_sdk_s3;
+#[cfg(all(test, any(feature = "http", feature = "s3", feature = "webdav")))]
+extern crate mockito;
+#[cfg(feature = "chacha20poly1305")]
+extern crate chacha20poly1305;
+#[cfg(feature = "flate2")]
+extern crate flate2;
+#[cfg(feature = "embedded")]
+extern crate include_dir;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "notify")]
+extern crate notify;
+#[cfg(feature = "regex")]
+extern crate regex;
+#[cfg(feature = "reqwest")]
+extern crate reqwest;
+#[cfg(feature = "sha2")]
+extern crate sha2;
+#[cfg(feature = "smartstring")]
+extern crate smartstring;
+#[cfg(feature = "tar")]
+extern crate tar;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+
+/// Build a `PathBuf` by pushing each segment in turn.
+///
+/// Segments can be anything implementing `AsRef<Path>`, so
+/// `path!["a", "b/c"]` is the path "a/b/c".
+#[macro_export]
+macro_rules! path {
+    () => { $crate::PathBuf::new() };
+    ($($seg:expr),+ $(,)*) => {{
+        let mut p = $crate::PathBuf::new();
+        $( p.push($seg); )+
+        p
+    }};
+}
+
 mod path;
 mod fs;
+mod interner;
+mod tree;
 mod native;
+mod mem;
+mod kv;
+mod counting;
+mod cached;
+mod contentcache;
+mod null;
+mod layers;
+mod dryrun;
+mod copyonwrite;
+mod throttled;
+mod watch;
+mod retry;
+mod tee;
+#[cfg(feature = "embedded")]
+mod embedded;
+#[cfg(feature = "encrypted")]
+mod encrypted;
+#[cfg(feature = "tokio")]
+mod asyncfs;
+#[cfg(feature = "sha2")]
+mod dedup;
+#[cfg(feature = "flate2")]
+mod decompress;
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "tar")]
+mod tarfs;
+#[cfg(feature = "sha2")]
+mod verified;
+#[cfg(feature = "webdav")]
+mod webdav;
+
+pub use path::{Path, PathBuf, PathBuilder, PathRange, Components, Indices, Iter, IntoIter};
+pub use fs::{Error, Result, FSRead, FSWrite, FileType, Metadata, OpenOptions, Permissions, QPath, DirEntries, ReadSeek};
+pub use fs::{DirEntriesExt, OfType, Visible, Requalify, Walk, WalkBuilder, Walker, copy_between, copy_tree_between};
+#[cfg(feature = "regex")]
+pub use fs::{Matching};
+pub use interner::{Interner, InternedPath};
+pub use tree::{Tree};
+pub use native::{Native, NativeBuilder};
+pub use mem::{MemFS, MemFile};
+pub use kv::{KvFS, KvFile, KvStore};
+pub use counting::{Counting};
+pub use cached::{Cached};
+pub use contentcache::{ContentCache, ContentFile};
+pub use null::{Null};
+pub use dryrun::{DryRun, DryRunFile, Operation};
+pub use copyonwrite::{CopyOnWrite, CopyOnWriteFile};
+pub use throttled::{Throttled, ThrottledFile};
+pub use retry::{Retry};
+pub use tee::{Tee, TeeFile};
+pub use watch::{Watch, WatchEvent, WatchEvents};
+#[cfg(feature = "mmap")]
+pub use native::{Mmap};
+#[cfg(feature = "embedded")]
+pub use embedded::{EmbeddedFS};
+#[cfg(feature = "encrypted")]
+pub use encrypted::{Encrypted, EncryptedFile};
+#[cfg(feature = "tokio")]
+pub use asyncfs::{AsyncFSRead, BoxFuture, TokioNative};
+#[cfg(feature = "sha2")]
+pub use dedup::{Dedup};
+#[cfg(feature = "flate2")]
+pub use decompress::{Decompress, DecompressFile};
+#[cfg(feature = "http")]
+pub use http::{HttpFS};
+#[cfg(feature = "s3")]
+pub use s3::{S3FS, S3File};
+#[cfg(feature = "tar")]
+pub use tarfs::{TarFS};
+#[cfg(feature = "sha2")]
+pub use verified::{Verified, VerifiedFile};
+#[cfg(feature = "webdav")]
+pub use webdav::{WebDavFS, WebDavFile};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
 
-pub use path::{Path, PathBuf, Components};
-pub use fs::{Error, Result, FSRead, FSWrite, FileType, QPath, DirEntries};
-pub use native::{Native};
+    #[test]
+    fn backends_send_sync() {
+        assert_send_sync::<Native>();
+        assert_send_sync::<MemFS>();
+        assert_send_sync::<QPath<Native>>();
+        assert_send_sync::<PathBuf>();
+        #[cfg(feature = "embedded")]
+        assert_send_sync::<EmbeddedFS>();
+    }
+}
@@ -0,0 +1,785 @@
+use std::{io, vec};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Metadata, OpenOptions, QPath};
+
+/// An in-memory filesystem.
+///
+/// Everything lives in a map from paths to file contents, so this is
+/// useful for tests and for staging output before it is written
+/// somewhere more permanent. Like `Native`, files can only be created
+/// inside directories that already exist.
+///
+/// `MemFS` is `Send` and `Sync`; all access goes through an internal
+/// lock, so it can be shared between threads in an `Arc`.
+pub struct MemFS {
+    nodes: Mutex<BTreeMap<String, Node>>,
+}
+
+#[derive(Clone)]
+enum Node {
+    Dir,
+    File(Arc<Mutex<Vec<u8>>>),
+}
+
+// paths are stored as their components joined by "/", so "" is the root
+fn key<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref().iter().collect::<Vec<_>>().join("/")
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+impl MemFS {
+    pub fn new() -> MemFS {
+        MemFS { nodes: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Build a filesystem holding the given files.
+    ///
+    /// Parent directories are created as needed. A path that is also
+    /// the parent of another file, or is the root, can't be a file, and
+    /// is an error.
+    pub fn from_map(files: BTreeMap<PathBuf, Vec<u8>>) -> Result<MemFS> {
+        let mut nodes = BTreeMap::new();
+        for path in files.keys() {
+            let mut dir = path.parent();
+            while let Some(d) = dir.filter(|d| !d.is_empty()) {
+                nodes.insert(key(d), Node::Dir);
+                dir = d.parent();
+            }
+        }
+        for (path, data) in files {
+            let k = key(&path);
+            if k.is_empty() || nodes.contains_key(&k) {
+                return Err(error("create", path, io::ErrorKind::Other, "Is a directory."));
+            }
+            nodes.insert(k, Node::File(Arc::new(Mutex::new(data))));
+        }
+        Ok(MemFS { nodes: Mutex::new(nodes) })
+    }
+
+    /// Take out every file and its contents.
+    ///
+    /// Directories are only implied by the files in them, so empty
+    /// directories are left out.
+    pub fn into_map(self) -> BTreeMap<PathBuf, Vec<u8>> {
+        self.nodes.into_inner().unwrap().into_iter().filter_map(|(k, node)| match node {
+            Node::File(data) => Some((PathBuf::from(k), data.lock().unwrap().clone())),
+            Node::Dir => None,
+        }).collect()
+    }
+
+    fn node<P: AsRef<Path>>(&self, path: P) -> Option<Node> {
+        let k = key(path);
+        if k.is_empty() {
+            return Some(Node::Dir);
+        }
+        self.nodes.lock().unwrap().get(&k).cloned()
+    }
+
+    // make sure the parent of path is an existing directory
+    fn check_parent<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<()> {
+        let parent = path.as_ref().parent().unwrap_or(Path::new(""));
+        match self.node(parent) {
+            Some(Node::Dir) => Ok(()),
+            Some(Node::File(_)) => Err(error(op, path, io::ErrorKind::Other, "Not a directory.")),
+            None => Err(error(op, path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+}
+
+impl Default for MemFS {
+    fn default() -> MemFS {
+        MemFS::new()
+    }
+}
+
+/// A writable handle to a file in a `MemFS`.
+pub struct MemFile {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl io::Write for MemFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub struct ReadDir<'a> {
+    iter: vec::IntoIter<PathBuf>,
+    parent: &'a MemFS,
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = QPath<'a, MemFS>;
+
+    fn next(&mut self) -> Option<QPath<'a, MemFS>> {
+        self.iter.next().map(|p| self.parent.qualified(p))
+    }
+}
+
+impl<'a> FSRead<'a> for MemFS {
+    type ReadFile = io::Cursor<Vec<u8>>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Cursor<Vec<u8>>> {
+        match self.node(&path) {
+            Some(Node::File(data)) => Ok(io::Cursor::new(data.lock().unwrap().clone())),
+            Some(Node::Dir) => Err(error("open", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("open", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        match self.node(&path) {
+            Some(Node::File(_)) => Ok(FileType::File),
+            Some(Node::Dir) => Ok(FileType::Dir),
+            None => Err(error("stat", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        match self.node(&path) {
+            Some(Node::File(data)) => Ok(Metadata::new(FileType::File, data.lock().unwrap().len() as u64, None)),
+            Some(Node::Dir) => Ok(Metadata::new(FileType::Dir, 0, None)),
+            None => Err(error("stat", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    type ReadDir = ReadDir<'a>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a>> {
+        match self.node(&path) {
+            Some(Node::Dir) => {}
+            Some(Node::File(_)) => return Err(error("read directory", path, io::ErrorKind::Other, "Not a directory.")),
+            None => return Err(error("read directory", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+        let dir = key(path);
+        let prefix = if dir.is_empty() { dir } else { dir + "/" };
+        let nodes = self.nodes.lock().unwrap();
+        let children: Vec<PathBuf> = nodes.keys()
+            .filter(|k| k.starts_with(&prefix) && !k[prefix.len()..].contains('/'))
+            .map(PathBuf::from)
+            .collect();
+        Ok(ReadDir { iter: children.into_iter(), parent: self })
+    }
+}
+
+impl<'a> FSWrite<'a> for MemFS {
+    type WriteFile = MemFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<MemFile> {
+        self.check_parent("create", &path)?;
+        if path.as_ref().is_empty() {
+            return Err(error("create", path, io::ErrorKind::Other, "Is a directory."));
+        }
+        let k = key(&path);
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(node) = nodes.get(&k) {
+            return match *node {
+                Node::Dir => Err(error("create", path, io::ErrorKind::Other, "Is a directory.")),
+                Node::File(ref data) => {
+                    data.lock().unwrap().clear();
+                    Ok(MemFile { data: data.clone() })
+                }
+            };
+        }
+        let data = Arc::new(Mutex::new(Vec::new()));
+        nodes.insert(k, Node::File(data.clone()));
+        Ok(MemFile { data: data })
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<MemFile> {
+        match self.node(&path) {
+            Some(Node::File(data)) => Ok(MemFile { data: data }),
+            Some(Node::Dir) => Err(error("append to", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("append to", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<MemFile> {
+        opts.check(&path)?;
+        let exists = self.node(&path).is_some();
+        if opts.create_new && exists {
+            return Err(error("open", path, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        if !exists && !opts.create && !opts.create_new {
+            return Err(error("open", path, io::ErrorKind::NotFound, "File not found."));
+        }
+        if opts.append && exists {
+            self.append(path)
+        } else if opts.append || opts.truncate || !exists {
+            self.create(path)
+        } else {
+            // files here can only be written from the end
+            Err(error("open", path, io::ErrorKind::Unsupported, "Writing in place not supported."))
+        }
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        match self.node(&path) {
+            Some(Node::File(data)) => {
+                data.lock().unwrap().resize(len as usize, 0);
+                Ok(())
+            }
+            Some(Node::Dir) => Err(error("truncate", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("truncate", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        let data = match self.node(&existing) {
+            Some(Node::File(data)) => data,
+            Some(Node::Dir) => return Err(error("link", existing, io::ErrorKind::Other, "Is a directory.")),
+            None => return Err(error("link", existing, io::ErrorKind::NotFound, "File not found.")),
+        };
+        self.check_parent("link", &link)?;
+        if self.node(&link).is_some() {
+            return Err(error("link", link, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        self.nodes.lock().unwrap().insert(key(link), Node::File(data));
+        Ok(())
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        let node = match self.node(&from) {
+            Some(_) if from.as_ref().is_empty() => {
+                return Err(error("rename", from, io::ErrorKind::PermissionDenied, "Cannot move the root."));
+            }
+            Some(node) => node,
+            None => return Err(error("rename", from, io::ErrorKind::NotFound, "File not found.")),
+        };
+        self.check_parent("rename", &to)?;
+        let (old, new) = (key(&from), key(&to));
+        if new == old {
+            return Ok(());
+        }
+        if new.starts_with(&(old.clone() + "/")) {
+            return Err(error("rename", from, io::ErrorKind::InvalidInput, "Cannot move a directory into itself."));
+        }
+        let mut nodes = self.nodes.lock().unwrap();
+        match (nodes.get(&new), &node) {
+            (Some(&Node::Dir), _) | (Some(&Node::File(_)), &Node::Dir) => {
+                return Err(error("rename", to, io::ErrorKind::AlreadyExists, "File exists."));
+            }
+            _ => {}
+        }
+        // move the node itself, then everything underneath it
+        let prefix = old.clone() + "/";
+        let children: Vec<String> = nodes.range(prefix.clone()..)
+            .map(|(k, _)| k)
+            .take_while(|k| k.starts_with(&prefix))
+            .cloned()
+            .collect();
+        nodes.remove(&old);
+        nodes.insert(new.clone(), node);
+        for k in children {
+            let child = nodes.remove(&k).unwrap();
+            nodes.insert(format!("{}/{}", new, &k[prefix.len()..]), child);
+        }
+        Ok(())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self.node(&path) {
+            Some(Node::File(_)) => {
+                self.nodes.lock().unwrap().remove(&key(path));
+                Ok(())
+            }
+            Some(Node::Dir) => Err(error("remove", path, io::ErrorKind::Other, "Is a directory.")),
+            None => Err(error("remove", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        match self.node(&path) {
+            Some(Node::Dir) if path.as_ref().is_empty() => {
+                Err(error("remove", path, io::ErrorKind::PermissionDenied, "Cannot remove the root."))
+            }
+            Some(Node::Dir) => {
+                let k = key(&path);
+                let prefix = k.clone() + "/";
+                let mut nodes = self.nodes.lock().unwrap();
+                if nodes.range(prefix.clone()..).next().is_some_and(|(c, _)| c.starts_with(&prefix)) {
+                    return Err(error("remove", path, io::ErrorKind::Other, "Directory not empty."));
+                }
+                nodes.remove(&k);
+                Ok(())
+            }
+            Some(Node::File(_)) => Err(error("remove", path, io::ErrorKind::Other, "Not a directory.")),
+            None => Err(error("remove", path, io::ErrorKind::NotFound, "File not found.")),
+        }
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.check_parent("create directory", &path)?;
+        if self.node(&path).is_some() {
+            return Err(error("create directory", path, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        self.nodes.lock().unwrap().insert(key(path), Node::Dir);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, WalkBuilder};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn mem_readwrite() {
+        let m = MemFS::new();
+        m.create("foo").unwrap().write_all(b"test").unwrap();
+        m.append("foo").unwrap().write_all(b"ing").unwrap();
+        let mut s = String::new();
+        m.open("/foo").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "testing");
+        assert!(m.is_file("foo"));
+        assert!(m.is_dir(""));
+        assert!(m.create("a/b").is_err());
+        assert!(m.open("bar").is_err());
+
+        m.hard_link("foo", "bar").unwrap();
+        m.append("bar").unwrap().write_all(b"!").unwrap();
+        let mut s = String::new();
+        m.open("foo").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "testing!");
+
+        m.truncate("foo", 4).unwrap();
+        let mut s = String::new();
+        m.open("foo").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "test");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn mem_read_dir_matching() {
+        use regex::Regex;
+
+        let m = MemFS::new();
+        m.create_dir("tiles").unwrap();
+        for name in &["tile_1.png", "tile_23.png", "tile_x.png", "tile_4.png.bak", "other.png"] {
+            m.create(Path::new("tiles").join(name)).unwrap();
+        }
+        m.create_dir("tiles/tile_5.png").unwrap();
+        let re = Regex::new(r"^tile_\d+\.png$").unwrap();
+        let names: Vec<PathBuf> = m.read_dir_matching("tiles", &re).unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(names, vec!["tiles/tile_1.png", "tiles/tile_23.png", "tiles/tile_5.png"]);
+        // the pattern only ever sees file names
+        let re = Regex::new("^tiles").unwrap();
+        assert_eq!(m.read_dir_matching("tiles", &re).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn mem_file_types() {
+        let m = MemFS::new();
+        m.create("f").unwrap();
+        m.create_dir("d").unwrap();
+        let types = m.file_types(vec!["f", "missing", "d"]);
+        assert_eq!(types.len(), 3);
+        assert!(types[0].as_ref().unwrap().is_file());
+        assert!(types[1].is_err());
+        assert!(types[2].as_ref().unwrap().is_dir());
+    }
+
+    #[test]
+    fn mem_canonicalize() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b").unwrap();
+        assert_eq!(m.canonicalize("a/./b/../b").unwrap(), "a/b");
+        assert!(m.canonicalize("a/..").unwrap().is_root());
+        assert!(m.canonicalize("a/../..").is_err());
+        assert!(m.canonicalize("a/c/..").is_ok());
+        assert!(m.canonicalize("a/c").is_err());
+    }
+
+    #[test]
+    fn mem_remove() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b").unwrap();
+        m.create("a/x").unwrap();
+        assert!(m.remove_dir("a").is_err());
+        assert!(m.remove_file("a/b").is_err());
+        assert!(m.remove_dir("").is_err());
+        m.remove_file("a/x").unwrap();
+        m.remove_dir("a/b").unwrap();
+        m.remove_dir("a").unwrap();
+        assert!(!m.exists("a"));
+        assert!(m.remove_file("a/x").is_err());
+    }
+
+    #[test]
+    fn mem_rename() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b").unwrap();
+        m.create("a/b/x").unwrap().write_all(b"x").unwrap();
+        m.create("y").unwrap();
+        assert!(m.rename("y", "c/d/y").is_err());
+        m.rename_create("y", "c/d/y").unwrap();
+        assert!(!m.exists("y"));
+        assert!(m.is_file("c/d/y"));
+
+        m.rename("a", "c/a").unwrap();
+        assert!(!m.exists("a"));
+        assert!(!m.exists("a/b/x"));
+        let mut s = String::new();
+        m.open("c/a/b/x").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "x");
+        assert!(m.rename("c", "c/a/c").is_err());
+        assert!(m.rename("missing", "z").is_err());
+    }
+
+    #[test]
+    fn mem_read_dir() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b").unwrap();
+        m.create("a/x").unwrap();
+        m.create("a/b/y").unwrap();
+        m.create("z").unwrap();
+        assert!(m.is_dir("a/b"));
+        let a: Vec<PathBuf> = m.read_dir("a").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(a, vec!["a/b", "a/x"]);
+        let root: Vec<PathBuf> = m.read_dir("/").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(root, vec!["a", "z"]);
+    }
+
+    #[test]
+    fn mem_qpath_navigate() {
+        let m = MemFS::new();
+        m.create_dir("a").unwrap();
+        m.create("a/x").unwrap().write_all(b"x").unwrap();
+        let dir = m.read_dir("").unwrap().next().unwrap();
+        let x = dir.join("x");
+        assert_eq!(x.path(), "a/x");
+        let mut s = String::new();
+        x.open().unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "x");
+        assert_eq!(x.parent().unwrap(), dir);
+        assert!(x.parent().unwrap().parent().unwrap().path().is_root());
+    }
+
+    #[test]
+    fn mem_qpath_read_range() {
+        let m = MemFS::new();
+        m.create("data").unwrap().write_all(b"0123456789").unwrap();
+        let q = m.qualified("data");
+        assert_eq!(q.read_range(2, 4).unwrap(), b"2345");
+        assert_eq!(q.read_range(0, 0).unwrap(), b"");
+        assert_eq!(q.read_range(6, 4).unwrap(), b"6789");
+        assert_eq!(q.read_range(8, 4).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(q.read_range(20, 1).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn mem_qpath_lines() {
+        let m = MemFS::new();
+        m.create("log").unwrap().write_all(b"first\nsecond\r\nthird").unwrap();
+        let lines: Vec<String> = m.qualified("log").lines().unwrap().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["first", "second", "third"]);
+
+        m.create("bad").unwrap().write_all(b"ok\n\xff\n").unwrap();
+        let mut lines = m.qualified("bad").lines().unwrap();
+        assert_eq!(lines.next().unwrap().unwrap(), "ok");
+        assert_eq!(lines.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(m.qualified("missing").lines().is_err());
+    }
+
+    #[test]
+    fn mem_qpath_starts_with() {
+        let m = MemFS::new();
+        m.create_dir_all("tiles/0").unwrap();
+        m.create_dir("tiles-old").unwrap();
+        m.create("tiles/0/a.png").unwrap();
+        m.create("tiles/b.png").unwrap();
+        m.create("tiles-old/c.png").unwrap();
+        let under: Vec<PathBuf> = m.walk("").unwrap().map(|e| e.unwrap())
+            .filter(|e| e.starts_with("tiles") && e.path().extension() == Some("png"))
+            .map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(under, vec!["tiles/0/a.png", "tiles/b.png"]);
+        let top: Vec<PathBuf> = m.read_dir("").unwrap().filter(|e| e.starts_with("tiles"))
+            .map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(top, vec!["tiles"]);
+        assert!(m.qualified("tiles/0/a.png").ends_with("0/a.png"));
+        assert!(!m.qualified("tiles/0/a.png").ends_with("png"));
+    }
+
+    #[test]
+    fn mem_read_dir_visible() {
+        use ::DirEntriesExt;
+
+        let m = MemFS::new();
+        m.create_dir_all("a/.b").unwrap();
+        m.create("a/.DS_Store").unwrap();
+        m.create("a/visible.txt").unwrap();
+        m.create("a/.b/c").unwrap();
+        let names: Vec<PathBuf> = m.read_dir("a").unwrap().visible().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(names, vec!["a/visible.txt"]);
+        assert_eq!(m.read_dir("a/.b").unwrap().visible().count(), 1);
+    }
+
+    #[test]
+    fn mem_open_meta() {
+        let m = MemFS::new();
+        m.create("a").unwrap().write_all(b"hello").unwrap();
+        let (mut f, meta) = m.open_meta("a").unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.len(), 5);
+        assert!(!meta.is_empty());
+        assert_eq!(meta.modified(), None);
+        let mut v = Vec::new();
+        f.read_to_end(&mut v).unwrap();
+        assert_eq!(v.len() as u64, meta.len());
+        assert!(m.metadata("").unwrap().is_dir());
+        m.create("empty").unwrap();
+        assert!(m.metadata("empty").unwrap().is_empty());
+    }
+
+    #[test]
+    fn mem_walk() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b/c").unwrap();
+        m.create_dir("d").unwrap();
+        m.create("a/x").unwrap();
+        m.create("a/b/y").unwrap();
+        let all: Vec<PathBuf> = m.walk("").unwrap().map(|e| e.unwrap().path().to_path_buf()).collect();
+        assert_eq!(all, vec!["a", "a/b", "a/b/c", "a/b/y", "a/x", "d"]);
+        let sub: Vec<PathBuf> = m.walk("a/b").unwrap().map(|e| e.unwrap().path().to_path_buf()).collect();
+        assert_eq!(sub, vec!["a/b/c", "a/b/y"]);
+        assert!(m.walk("a/x").is_err());
+    }
+
+    #[test]
+    fn mem_read_dir_recursive_flat() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b/c").unwrap();
+        m.create("a/x").unwrap();
+        m.create("a/b/y").unwrap();
+        m.create("z").unwrap();
+        let files: Vec<PathBuf> = m.read_dir_recursive_flat("").unwrap().map(|e| e.unwrap().path().to_path_buf()).collect();
+        let walked: Vec<PathBuf> = m.walk("").unwrap().map(|e| e.unwrap())
+            .filter(|e| e.is_file()).map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(files, walked);
+        assert_eq!(files, vec!["a/b/y", "a/x", "z"]);
+        assert!(m.read_dir_recursive_flat("z").is_err());
+    }
+
+    #[test]
+    fn mem_walk_builder() {
+        let m = MemFS::new();
+        m.create_dir_all("a/skip/deep").unwrap();
+        m.create_dir_all("a/keep/deep").unwrap();
+        m.create("a/skip/x").unwrap();
+        m.create("a/skip/deep/y").unwrap();
+        m.create("a/keep/x").unwrap();
+        m.create("a/keep/deep/y").unwrap();
+        m.create("a/z").unwrap();
+        let walk = |b: WalkBuilder<MemFS>| -> Vec<PathBuf> {
+            b.build().unwrap().map(|p| p.unwrap().path().to_path_buf()).collect()
+        };
+
+        let pruned = walk(m.walk_builder("a").filter_entry(|p| p.path().file_name() != Some("skip")).sort_by(|a, b| a.cmp(b)));
+        assert_eq!(pruned, vec!["a/keep", "a/keep/deep", "a/keep/deep/y", "a/keep/x", "a/z"]);
+        assert!(pruned.iter().all(|p| !p.contains("skip")));
+
+        let shallow = walk(m.walk_builder("a").max_depth(1).sort_by(|a, b| b.cmp(a)));
+        assert_eq!(shallow, vec!["a/z", "a/skip", "a/keep"]);
+        let deep = walk(m.walk_builder("a").min_depth(3));
+        assert_eq!(deep, vec!["a/keep/deep/y", "a/skip/deep/y"]);
+        assert_eq!(walk(m.walk_builder("a")).len(), m.walk("a").unwrap().count());
+        assert!(walk(m.walk_builder("a").max_depth(0)).is_empty());
+        assert!(m.walk_builder("missing").build().is_err());
+    }
+
+    #[test]
+    fn mem_plan_remove() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b/c").unwrap();
+        m.create("a/x").unwrap();
+        m.create("a/b/y").unwrap();
+        m.create("keep").unwrap();
+        let plan = m.plan_remove("a").unwrap();
+        assert_eq!(plan, vec!["a/x", "a/b/y", "a/b/c", "a/b", "a"]);
+        for (i, p) in plan.iter().enumerate() {
+            assert!(plan[..i].iter().all(|q| !p.starts_with(q)));
+        }
+        assert!(m.is_file("a/x") && m.is_dir("a/b/c"));
+
+        assert_eq!(m.plan_remove("a/b/y").unwrap(), vec!["a/b/y"]);
+        assert_eq!(m.plan_remove("a/b/c").unwrap(), vec!["a/b/c"]);
+        assert!(m.plan_remove("missing").is_err());
+    }
+
+    #[test]
+    fn mem_disk_usage() {
+        let m = MemFS::new();
+        m.create_dir_all("a/b").unwrap();
+        m.create_dir("a/empty").unwrap();
+        m.create("a/one").unwrap().write_all(&[0; 10]).unwrap();
+        m.create("a/b/two").unwrap().write_all(&[0; 20]).unwrap();
+        m.create("three").unwrap().write_all(&[0; 5]).unwrap();
+        assert_eq!(m.disk_usage("").unwrap(), 35);
+        assert_eq!(m.disk_usage("a").unwrap(), 30);
+        assert_eq!(m.disk_usage("a/empty").unwrap(), 0);
+        assert_eq!(m.disk_usage("three").unwrap(), 5);
+        assert!(m.disk_usage("missing").is_err());
+    }
+
+    #[test]
+    fn mem_open_options() {
+        use ::OpenOptions;
+
+        let m = MemFS::new();
+        let new = OpenOptions::new().write(true).create_new(true);
+        m.open_options("lock", new).unwrap().write_all(b"held").unwrap();
+        assert_eq!(m.open_options("lock", new).err().unwrap().kind(), io::ErrorKind::AlreadyExists);
+
+        m.open_options("lock", OpenOptions::new().append(true)).unwrap().write_all(b"!").unwrap();
+        m.open_options("log", OpenOptions::new().append(true).create(true)).unwrap().write_all(b"a").unwrap();
+        let mut s = String::new();
+        m.open("lock").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "held!");
+        assert!(m.is_file("log"));
+
+        let kind = |opts| m.open_options("lock", opts).err().unwrap().kind();
+        assert_eq!(kind(OpenOptions::new().write(true)), io::ErrorKind::Unsupported);
+        assert_eq!(kind(OpenOptions::new().truncate(true).append(true)), io::ErrorKind::InvalidInput);
+        assert_eq!(kind(OpenOptions::new().create(true)), io::ErrorKind::InvalidInput);
+        assert_eq!(m.open_options("missing", OpenOptions::new().write(true)).err().unwrap().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn mem_qpath_strip_prefix() {
+        let m = MemFS::new();
+        m.create_dir_all("root/sub").unwrap();
+        m.create("root/sub/x").unwrap().write_all(b"x").unwrap();
+        let mut found = Vec::new();
+        for entry in m.walk("root").unwrap() {
+            let short = entry.unwrap().strip_prefix("root").unwrap();
+            if short.is_file() {
+                let mut s = String::new();
+                short.open().unwrap().read_to_string(&mut s).unwrap();
+                assert_eq!(s, "x");
+            }
+            found.push(short);
+        }
+        assert_eq!(found.iter().map(|q| q.path()).collect::<Vec<_>>(), vec!["sub", "sub/x"]);
+        let x = &found[1];
+        assert!(x.parent().unwrap().is_dir());
+        assert!(x.parent().unwrap().parent().unwrap().path().is_empty());
+        assert!(x.strip_prefix("sub").unwrap().is_file());
+        assert!(x.strip_prefix("root").is_none());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn mem_manifest() {
+        use sha2::{Digest, Sha256};
+
+        let m = MemFS::new();
+        m.create_dir_all("bundle/img").unwrap();
+        m.create_dir("bundle/empty").unwrap();
+        m.create("bundle/index.html").unwrap().write_all(b"<html>").unwrap();
+        m.create("bundle/img/a.png").unwrap().write_all(&[1; 100]).unwrap();
+        m.create("outside").unwrap();
+        let manifest = m.manifest("bundle").unwrap();
+        let summary: Vec<(&str, u64)> = manifest.iter().map(|&(ref p, len, _)| (p.as_str(), len)).collect();
+        assert_eq!(summary, vec![("img/a.png", 100), ("index.html", 6)]);
+        assert_eq!(&manifest[1].2[..], &Sha256::digest(b"<html>")[..]);
+    }
+
+    #[test]
+    fn mem_copy_tree_parallel() {
+        let m = MemFS::new();
+        m.create_dir_all("src/tiles/0").unwrap();
+        m.create_dir("src/empty").unwrap();
+        for i in 0..20 {
+            write!(m.create(format!("src/tiles/0/{}.png", i)).unwrap(), "tile {}", i).unwrap();
+        }
+        m.create("src/index.html").unwrap().write_all(b"<html>").unwrap();
+        m.copy_tree_parallel("src", "out/site", 4).unwrap();
+
+        assert!(m.is_dir("out/site/empty"));
+        assert_eq!(m.disk_usage("out/site").unwrap(), m.disk_usage("src").unwrap());
+        for i in 0..20 {
+            let mut s = String::new();
+            m.open(format!("out/site/tiles/0/{}.png", i)).unwrap().read_to_string(&mut s).unwrap();
+            assert_eq!(s, format!("tile {}", i));
+        }
+        // copying over an existing tree replaces its files
+        m.create("src/index.html").unwrap().write_all(b"<html>!").unwrap();
+        m.copy_tree_parallel("src", "out/site", 4).unwrap();
+        assert_eq!(m.disk_usage("out/site").unwrap(), m.disk_usage("src").unwrap());
+        assert_eq!(m.copy_tree_parallel("missing", "out", 4).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn mem_create_writer() {
+        let m = MemFS::new();
+        let value = ::serde_json::json!({"name": "overworld", "zoom": [0, 1, 2]});
+        let mut w = m.create_writer("world.json").unwrap();
+        ::serde_json::to_writer(&mut w, &value).unwrap();
+        drop(w);
+        let read: ::serde_json::Value = ::serde_json::from_reader(m.open("world.json").unwrap()).unwrap();
+        assert_eq!(read, value);
+    }
+
+    #[test]
+    fn mem_map_round_trip() {
+        // a small deterministic generator, so failures are reproducible
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        for _ in 0..50 {
+            let mut files = BTreeMap::new();
+            for _ in 0..next(10) {
+                let path: PathBuf = (0..next(3) + 1).map(|_| format!("f{}", next(4))).collect::<Vec<_>>().join("/").into();
+                let data: Vec<u8> = (0..next(16)).map(|_| next(256) as u8).collect();
+                files.insert(path, data);
+            }
+            // a file can't also be the parent of another
+            let clash = files.keys().any(|p| files.keys().any(|q| q != p && q.starts_with(p)));
+            let m = match MemFS::from_map(files.clone()) {
+                Ok(m) => m,
+                Err(e) => {
+                    assert!(clash, "{}", e);
+                    continue;
+                }
+            };
+            assert!(!clash);
+            for (path, data) in &files {
+                let mut read = Vec::new();
+                m.open(path).unwrap().read_to_end(&mut read).unwrap();
+                assert_eq!(&read, data);
+            }
+            assert_eq!(m.into_map(), files);
+        }
+
+        let mut files = BTreeMap::new();
+        files.insert(PathBuf::from("a"), b"a".to_vec());
+        files.insert(PathBuf::from("a/b"), b"b".to_vec());
+        let err = MemFS::from_map(files).err().unwrap();
+        assert_eq!(err.path(), Some(Path::new("a")));
+        let mut files = BTreeMap::new();
+        files.insert(PathBuf::new(), b"root".to_vec());
+        assert!(MemFS::from_map(files).is_err());
+
+        let m = MemFS::new();
+        m.create_dir_all("a/empty").unwrap();
+        m.create("a/b").unwrap().write_all(b"b").unwrap();
+        let map = m.into_map();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.get(Path::new("a/b")).map(|d| &d[..]), Some(&b"b"[..]));
+    }
+}
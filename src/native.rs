@@ -1,35 +1,234 @@
-#[cfg(test)]
-extern crate tempdir;
-
 use std::{path, fs, io};
+#[cfg(feature = "mmap")]
+use std::{cmp, ops};
+#[cfg(feature = "mmap")]
+use memmap2;
+#[cfg(feature = "watch")]
+use std::sync::mpsc;
+#[cfg(feature = "watch")]
+use notify::{self, Watcher};
+#[cfg(feature = "watch")]
+use notify::event::{EventKind, ModifyKind, RenameMode};
+#[cfg(feature = "watch")]
+use watch::{Watch, WatchEvent, WatchEvents};
 use std::convert::From;
 use path::{Path, PathBuf};
-use fs::{FSRead, FSWrite, Result, FileType, QPath};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Metadata, OpenOptions, Permissions, QPath};
 
 /// A native, local filesystem.
 ///
 /// This object implements all the FS traits, and simply passes
 /// through operations to the local file system under a prefix.
+///
+/// `Native` is `Send` and `Sync`, so it can be shared between threads
+/// in an `Arc`. Each thread can then borrow it for `open`, `read_dir`
+/// and friends independently.
+///
+/// By default symlinks are followed, and paths with "." or ".."
+/// components are refused so they can't leave the prefix. Use
+/// `Native::builder` to change either.
 pub struct Native {
-    inner: path::PathBuf
+    inner: path::PathBuf,
+    follow_symlinks: bool,
+    allow_escape: bool,
+}
+
+/// Options for creating a `Native`.
+///
+/// This is produced by `Native::builder`.
+#[derive(Clone, Copy, Debug)]
+pub struct NativeBuilder {
+    follow_symlinks: bool,
+    allow_escape: bool,
+}
+
+impl NativeBuilder {
+    /// Whether paths may pass through symlinks under the prefix. If
+    /// not, any operation on such a path fails with
+    /// `PermissionDenied`. The prefix itself may still be a symlink.
+    pub fn follow_symlinks(mut self, follow: bool) -> NativeBuilder {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Whether "." and ".." components are passed through to the
+    /// operating system, where ".." can climb out of the prefix. If
+    /// not, such paths fail with `PermissionDenied`.
+    pub fn allow_escape(mut self, allow: bool) -> NativeBuilder {
+        self.allow_escape = allow;
+        self
+    }
+
+    /// Create a `Native` rooted at `path` with these options.
+    pub fn build<P: AsRef<path::Path>>(self, path: P) -> Native {
+        Native { inner: path.as_ref().to_path_buf(), follow_symlinks: self.follow_symlinks, allow_escape: self.allow_escape }
+    }
+}
+
+impl Default for NativeBuilder {
+    fn default() -> NativeBuilder {
+        NativeBuilder { follow_symlinks: true, allow_escape: false }
+    }
 }
 
 impl Native {
     pub fn new<P: AsRef<path::Path>>(path: P) -> Native {
-        Native { inner: path.as_ref().to_path_buf() }
+        Native::builder().build(path)
+    }
+
+    /// Configure a `Native` before creating it.
+    pub fn builder() -> NativeBuilder {
+        NativeBuilder::default()
     }
 
     fn path<P: AsRef<Path>>(&self, path: P) -> path::PathBuf {
-        let mut p = self.inner.clone();
+        let path = path.as_ref();
+        let len = self.inner.as_os_str().len() + path.as_str().len() + 1;
+        let mut p = path::PathBuf::with_capacity(len);
+        self.path_into(path, &mut p);
+        return p;
+    }
+
+    // like path(), but refuses paths that could escape the prefix, or
+    // pass through symlinks, unless configured otherwise
+    fn safe_path<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<path::PathBuf> {
+        let path = path.as_ref();
+        // on windows, std would treat these as separators or drive prefixes
+        let native_safe = !cfg!(windows) || !path.as_str().contains(&['\\', ':'][..]);
+        if !self.allow_escape && (!path.is_safe() || !native_safe) {
+            let err = io::Error::new(io::ErrorKind::PermissionDenied, "Path leaves the filesystem.");
+            return Err(Error::new(op, path, err));
+        }
+        if !self.follow_symlinks {
+            let mut p = self.inner.clone();
+            for part in path {
+                p.push(part.as_str());
+                match fs::symlink_metadata(&p) {
+                    Ok(ref m) if m.file_type().is_symlink() => {
+                        let err = io::Error::new(io::ErrorKind::PermissionDenied, "Path contains a symlink.");
+                        return Err(Error::new(op, path, err));
+                    }
+                    Ok(_) => {}
+                    // nothing further down exists to be a symlink
+                    Err(_) => break,
+                }
+            }
+        }
+        Ok(self.path(path))
+    }
+
+    // like path(), but reuses an existing buffer
+    fn path_into<P: AsRef<Path>>(&self, path: P, buf: &mut path::PathBuf) {
+        buf.as_mut_os_string().clear();
+        buf.push(&self.inner);
         for part in path.as_ref() {
-            p.push(part.as_str());
+            buf.push(part.as_str());
         }
-        return p;
     }
 
     fn unpath<P: AsRef<path::Path>>(&self, path: P) -> Option<PathBuf> {
         path.as_ref().relative_from(&self.inner).and_then(|p| p.to_str()).map(From::from)
     }
+
+    /// Open a file as a read-only memory map.
+    ///
+    /// This is often faster than `open` for large files that are
+    /// scanned repeatedly.
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap<P: AsRef<Path>>(&self, path: P) -> Result<Mmap> {
+        let map = fs::File::open(self.safe_path("map", &path)?).and_then(|f| unsafe { memmap2::Mmap::map(&f) });
+        map.map(|m| Mmap { map: m, pos: 0 }).map_err(|e| Error::new("map", path, e))
+    }
+}
+
+#[cfg(feature = "watch")]
+impl Watch for Native {
+    fn watch<P: AsRef<Path>>(&self, path: P) -> Result<WatchEvents> {
+        let fail = |e: notify::Error| Error::new("watch", &path, io::Error::new(io::ErrorKind::Other, e));
+        // some platforms report paths under the prefix as it was given,
+        // others fully resolved, so try both
+        let roots = [self.inner.clone(), fs::canonicalize(&self.inner).map_err(|e| Error::new("watch", &path, e))?];
+        let (tx, rx) = mpsc::channel();
+        // events are translated on the watcher's own thread, which
+        // stops when the watcher is dropped along with the receiver
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(_) => return,
+            };
+            let paths: Vec<PathBuf> = event.paths.iter().filter_map(|p| {
+                roots.iter().filter_map(|root| p.relative_from(root)).next().and_then(|p| p.to_str()).map(From::from)
+            }).collect();
+            let events: Vec<WatchEvent> = match event.kind {
+                EventKind::Create(_) => paths.into_iter().map(WatchEvent::Create).collect(),
+                EventKind::Remove(_) => paths.into_iter().map(WatchEvent::Remove).collect(),
+                EventKind::Modify(ModifyKind::Name(RenameMode::From)) => paths.into_iter().map(WatchEvent::Remove).collect(),
+                EventKind::Modify(ModifyKind::Name(RenameMode::To)) => paths.into_iter().map(WatchEvent::Create).collect(),
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if paths.len() == 2 => {
+                    vec![WatchEvent::Remove(paths[0].clone()), WatchEvent::Create(paths[1].clone())]
+                }
+                EventKind::Modify(_) => paths.into_iter().map(WatchEvent::Modify).collect(),
+                _ => return,
+            };
+            for e in events {
+                let _ = tx.send(e);
+            }
+        }).map_err(&fail)?;
+        watcher.watch(&self.safe_path("watch", &path)?, notify::RecursiveMode::Recursive).map_err(&fail)?;
+        Ok(WatchEvents::new(rx, watcher))
+    }
+}
+
+fn native_metadata(meta: &fs::Metadata) -> Metadata {
+    Metadata::new(meta.file_type().into(), meta.len(), meta.modified().ok())
+}
+
+/// A read-only memory-mapped file, from `Native::open_mmap`.
+///
+/// The mapped bytes are available through `Deref`, and the map can
+/// also be read through `io::Read` and `io::Seek`.
+#[cfg(feature = "mmap")]
+pub struct Mmap {
+    map: memmap2::Mmap,
+    pos: usize,
+}
+
+#[cfg(feature = "mmap")]
+impl ops::Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl io::Read for Mmap {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // seeking may leave pos past the end
+        let start = cmp::min(self.pos, self.map.len());
+        let n = io::Read::read(&mut &self.map[start..], buf)?;
+        self.pos = start + n;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl io::Seek for Mmap {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(n) => (0, n as i64),
+            io::SeekFrom::End(n) => (self.map.len() as i64, n),
+            io::SeekFrom::Current(n) => (self.pos as i64, n),
+        };
+        match base.checked_add(offset) {
+            Some(p) if p >= 0 => {
+                self.pos = p as usize;
+                Ok(p as u64)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek to a negative position.")),
+        }
+    }
 }
 
 pub struct ReadDir<'a> {
@@ -54,29 +253,173 @@ impl<'a> Iterator for ReadDir<'a> {
     }
 }
 
+// every file under a directory, using the types std reads with each
+// entry rather than looking each one up again
+struct Files<'a> {
+    stack: Vec<(PathBuf, fs::ReadDir)>,
+    parent: &'a Native,
+}
+
+impl<'a> Files<'a> {
+    fn descend(&mut self, dir: PathBuf, full: &path::Path) -> Result<()> {
+        let iter = fs::read_dir(full).map_err(|e| Error::new("read directory", &dir, e))?;
+        self.stack.push((dir, iter));
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for Files<'a> {
+    type Item = Result<QPath<'a, Native>>;
+
+    fn next(&mut self) -> Option<Result<QPath<'a, Native>>> {
+        loop {
+            let (dir, res) = match self.stack.last_mut() {
+                Some(&mut (ref dir, ref mut iter)) => (dir.clone(), iter.next()),
+                None => return None,
+            };
+            let entry = match res {
+                Some(Ok(entry)) => entry,
+                Some(Err(e)) => return Some(Err(Error::new("read directory", dir, e))),
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+            let p = match self.parent.unpath(entry.path()) {
+                Some(p) => p,
+                None => {
+                    let err = io::Error::new(io::ErrorKind::InvalidData, "File name is not valid UTF-8.");
+                    return Some(Err(Error::new("read directory", dir, err)));
+                }
+            };
+            let std_type = match entry.file_type() {
+                Ok(t) => t,
+                Err(e) => return Some(Err(Error::new("stat", p, e))),
+            };
+            if std_type.is_symlink() {
+                // looked up properly, to honour follow_symlinks, but
+                // never descended into
+                match self.parent.file_type(&p) {
+                    Ok(FileType::File) => return Some(Ok(self.parent.qualified(p))),
+                    Ok(FileType::Dir) => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            } else if std_type.is_dir() {
+                if let Err(e) = self.descend(p, &entry.path()) {
+                    return Some(Err(e));
+                }
+            } else if std_type.is_file() {
+                return Some(Ok(self.parent.qualified(p)));
+            }
+        }
+    }
+}
+
 impl<'a> FSRead<'a> for Native {
     type ReadFile = fs::File;
 
     fn open<P: AsRef<Path>>(&self, path: P) -> Result<fs::File> {
-        fs::File::open(self.path(path))
+        fs::File::open(self.safe_path("open", &path)?).map_err(|e| Error::new("open", path, e))
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(fs::File, Metadata)> {
+        let f = self.open(&path)?;
+        // stat the handle, not the path, in case the file was replaced
+        let meta = f.metadata().map_err(|e| Error::new("stat", &path, e))?;
+        Ok((f, native_metadata(&meta)))
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        let meta = fs::metadata(self.safe_path("stat", &path)?).map_err(|e| Error::new("stat", path, e))?;
+        Ok(native_metadata(&meta))
     }
 
     fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
-        let p = self.path(path);
-        if p.exists() {
-            if p.is_file() {
-                return Ok(FileType::File);
-            } else if p.is_dir() {
-                return Ok(FileType::Dir);
+        let meta = fs::metadata(self.safe_path("stat", &path)?).map_err(|e| Error::new("stat", &path, e))?;
+        // fifos, sockets and devices aren't files to read, and opening
+        // one may block
+        if !meta.is_file() && !meta.is_dir() {
+            let err = io::Error::new(io::ErrorKind::NotFound, "File not found.");
+            return Err(Error::new("stat", path, err));
+        }
+        Ok(meta.file_type().into())
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        // the parent is checked as usual, but the name itself may be a
+        // symlink even when they aren't followed
+        let name_safe = |n: &str| n != "." && n != ".." && (!cfg!(windows) || !n.contains(&['\\', ':'][..]));
+        match path.as_ref().split_last() {
+            Some((name, parent)) if self.allow_escape || name_safe(name) => match self.safe_path("stat", parent) {
+                Ok(p) => fs::symlink_metadata(p.join(name)).map(|m| m.file_type().is_symlink()).unwrap_or(false),
+                Err(_) => false,
+            },
+            _ => false,
+        }
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        let perm = fs::metadata(self.safe_path("stat", &path)?).map_err(|e| Error::new("stat", path, e))?.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            return Ok(Permissions::from_mode(perm.mode()));
+        }
+        #[cfg(not(unix))]
+        Ok(Permissions::new(perm.readonly()))
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        // resolve "." and ".." first, so the usual checks apply to what
+        // is left and a path can't probe outside the prefix
+        let mut parts = Vec::new();
+        for part in path.as_ref() {
+            match part.as_str() {
+                "." => {}
+                ".." if !self.allow_escape => if parts.pop().is_none() {
+                    let err = io::Error::new(io::ErrorKind::PermissionDenied, "Path leaves the filesystem.");
+                    return Err(Error::new("canonicalize", path, err));
+                },
+                s => parts.push(s),
             }
         }
-        return Err(io::Error::new(io::ErrorKind::NotFound, "File not found."));
+        let safe = self.safe_path("canonicalize", PathBuf::from(parts.join("/")))?;
+        let root = fs::canonicalize(&self.inner).map_err(|e| Error::new("canonicalize", &path, e))?;
+        let full = fs::canonicalize(safe).map_err(|e| Error::new("canonicalize", &path, e))?;
+        // symlinks may point anywhere, so check the result is still inside
+        full.relative_from(&root).and_then(|p| p.to_str()).map(From::from).ok_or_else(|| {
+            let err = io::Error::new(io::ErrorKind::PermissionDenied, "Path leaves the filesystem.");
+            Error::new("canonicalize", path, err)
+        })
     }
 
     type ReadDir = ReadDir<'a>;
 
     fn read_dir<P: AsRef<Path>>(&self, path: P) -> Result<ReadDir> {
-        self.path(path).read_dir().map(|dirs| ReadDir { iter: dirs, parent: self })
+        self.safe_path("read directory", &path)?.read_dir().map(|dirs| ReadDir { iter: dirs, parent: self })
+            .map_err(|e| Error::new("read directory", path, e))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Native>>> {
+        let dirs = self.safe_path("read directory", &path)?.read_dir().map_err(|e| Error::new("read directory", &path, e))?;
+        let mut entries = Vec::new();
+        for res in dirs {
+            let entry = res.map_err(|e| Error::new("read directory", &path, e))?;
+            let p = self.unpath(entry.path()).ok_or_else(|| {
+                let err = io::Error::new(io::ErrorKind::InvalidData, "File name is not valid UTF-8.");
+                Error::new("read directory", &path, err)
+            })?;
+            entries.push(self.qualified(p));
+        }
+        Ok(entries)
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Native>>> + 'a>
+    {
+        let mut files = Files { stack: Vec::new(), parent: self };
+        files.descend(prefix.as_ref().to_path_buf(), &self.safe_path("read directory", &prefix)?)?;
+        Ok(files)
     }
 }
 
@@ -84,13 +427,74 @@ impl<'a> FSWrite<'a> for Native {
     type WriteFile = fs::File;
 
     fn create<P: AsRef<Path>>(&self, path: P) -> Result<fs::File> {
-        fs::File::create(self.path(path))
+        fs::File::create(self.safe_path("create", &path)?).map_err(|e| Error::new("create", path, e))
     }
     
     fn append<P: AsRef<Path>>(&self, path: P) -> Result<fs::File> {
         use std::fs::OpenOptions;
 
-        OpenOptions::new().read(false).write(true).create(false).append(true).open(self.path(path))
+        OpenOptions::new().read(false).write(true).create(false).append(true).open(self.safe_path("append to", &path)?)
+            .map_err(|e| Error::new("append to", path, e))
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<fs::File> {
+        fs::OpenOptions::new()
+            .read(opts.read)
+            .write(opts.write)
+            .append(opts.append)
+            .create(opts.create)
+            .create_new(opts.create_new)
+            .truncate(opts.truncate)
+            .open(self.safe_path("open", &path)?)
+            .map_err(|e| Error::new("open", path, e))
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        fs::OpenOptions::new().write(true).open(self.safe_path("truncate", &path)?)
+            .and_then(|f| f.set_len(len))
+            .map_err(|e| Error::new("truncate", path, e))
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        let p = self.safe_path("set permissions on", &path)?;
+        let mut native = fs::metadata(&p).map_err(|e| Error::new("set permissions on", &path, e))?.permissions();
+        native.set_readonly(perm.readonly());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if let Some(mode) = perm.mode() {
+                native.set_mode(mode);
+            }
+        }
+        fs::set_permissions(p, native).map_err(|e| Error::new("set permissions on", path, e))
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        fs::hard_link(self.safe_path("link", &existing)?, self.safe_path("link", link)?).map_err(|e| Error::new("link", existing, e))
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        fs::rename(self.safe_path("rename", &from)?, self.safe_path("rename", to)?).map_err(|e| Error::new("rename", from, e))
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::remove_file(self.safe_path("remove", &path)?).map_err(|e| Error::new("remove", path, e))
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::remove_dir(self.safe_path("remove", &path)?).map_err(|e| Error::new("remove", path, e))
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::create_dir(self.safe_path("create directory", &path)?).map_err(|e| Error::new("create directory", path, e))
+    }
+
+    fn create_dir_all<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::create_dir_all(self.safe_path("create directory", &path)?).map_err(|e| Error::new("create directory", path, e))
+    }
+
+    fn copy<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<u64> {
+        fs::copy(self.safe_path("copy", &from)?, self.safe_path("copy", to)?).map_err(|e| Error::new("copy", from, e))
     }
 
 }
@@ -99,8 +503,9 @@ impl<'a> FSWrite<'a> for Native {
 #[cfg(test)]
 mod test {
     use super::*;
+    use std::path;
     use ::{FSWrite, FSRead};
-    use super::tempdir::TempDir;
+    use tempdir::TempDir;
     use std::io::{Write, Read};
 
 
@@ -122,4 +527,658 @@ mod test {
 
     }
 
+    #[test]
+    fn native_file_type() {
+        use ::FileType;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap();
+        n.create_dir("bar").unwrap();
+        assert_eq!(n.file_type("foo").unwrap(), FileType::File);
+        assert_eq!(n.file_type("bar").unwrap(), FileType::Dir);
+        assert_ne!(n.file_type("foo").unwrap(), n.file_type("bar").unwrap());
+        assert_eq!(format!("{:?}", FileType::File), "File");
+
+        let std_type = ::std::fs::metadata(t.path()).unwrap().file_type();
+        assert_eq!(FileType::from(std_type), FileType::Dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_file_type_special() {
+        use std::os::unix::net::UnixListener;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        let _sock = UnixListener::bind(t.path().join("sock")).unwrap();
+        assert_eq!(n.file_type("sock").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert!(!n.is_file("sock"));
+        assert!(!n.exists("sock"));
+        assert_eq!(n.read_dir_recursive_flat("").unwrap().count(), 0);
+    }
+
+    #[test]
+    fn native_read_dir_sorted() {
+        use ::DirEntriesExt;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        for name in &["c", "a-b", "b", "a"] {
+            n.create(name).unwrap();
+        }
+        let names: Vec<PathBuf> = n.read_dir("").unwrap().sorted().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(names, vec!["a", "a-b", "b", "c"]);
+    }
+
+    #[test]
+    fn native_read_dir_all() {
+        use ::DirEntriesExt;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create_dir("d").unwrap();
+        for name in &["d/a", "d/b", "d/c"] {
+            n.create(name).unwrap();
+        }
+        let mut names: Vec<PathBuf> = n.read_dir_all("d").unwrap().into_iter().map(|p| p.path().to_path_buf()).collect();
+        names.sort();
+        assert_eq!(names, n.read_dir("d").unwrap().sorted().map(|p| p.path().to_path_buf()).collect::<Vec<_>>());
+        assert_eq!(names, vec!["d/a", "d/b", "d/c"]);
+        assert_eq!(n.read_dir_all("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_plan_remove_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let t = TempDir::new("riotest").unwrap();
+        ::std::fs::create_dir_all(t.path().join("root/d/sub")).unwrap();
+        ::std::fs::create_dir(t.path().join("outside")).unwrap();
+        ::std::fs::File::create(t.path().join("outside/precious")).unwrap();
+        symlink(t.path().join("outside"), t.path().join("root/d/link")).unwrap();
+        symlink(t.path().join("root/d"), t.path().join("root/d/sub/cycle")).unwrap();
+        symlink(t.path().join("missing"), t.path().join("root/d/dangling")).unwrap();
+
+        for n in &[Native::new(t.path().join("root")), Native::builder().follow_symlinks(false).build(t.path().join("root"))] {
+            assert!(n.is_symlink("d/link") && n.is_symlink("d/dangling"));
+            assert!(!n.is_symlink("d") && !n.is_symlink("missing"));
+            let mut plan = n.plan_remove("d").unwrap();
+            assert_eq!(plan.pop().unwrap(), "d");
+            plan.sort();
+            assert_eq!(plan, vec!["d/dangling", "d/link", "d/sub", "d/sub/cycle"]);
+            assert_eq!(n.plan_remove("d/link").unwrap(), vec!["d/link"]);
+        }
+        let wrapped = ::Counting::new(Native::new(t.path().join("root")));
+        assert_eq!(wrapped.plan_remove("d").unwrap().len(), 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_disk_usage_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path().join("root"));
+        ::std::fs::create_dir_all(t.path().join("root/d")).unwrap();
+        ::std::fs::create_dir(t.path().join("outside")).unwrap();
+        ::std::fs::write(t.path().join("outside/precious"), b"x").unwrap();
+        ::std::fs::write(t.path().join("root/d/file"), b"four").unwrap();
+        symlink(t.path().join("outside"), t.path().join("root/d/link")).unwrap();
+        symlink(t.path().join("root/d/file"), t.path().join("root/d/filelink")).unwrap();
+        symlink(t.path().join("root/d"), t.path().join("root/d/cycle")).unwrap();
+
+        assert_eq!(n.disk_usage("d").unwrap(), 4);
+        assert_eq!(n.disk_usage("d/link").unwrap(), 0);
+        assert_eq!(n.disk_usage("d/file").unwrap(), 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_copy_tree_parallel_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create_dir_all("d/sub").unwrap();
+        n.create("d/file").unwrap().write_all(b"four").unwrap();
+        symlink(t.path().join("d/file"), t.path().join("d/filelink")).unwrap();
+        symlink(t.path().join("d"), t.path().join("d/sub/cycle")).unwrap();
+
+        n.copy_tree_parallel("d", "out", 2).unwrap();
+        let mut s = String::new();
+        n.open("out/filelink").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "four");
+        assert!(!n.is_symlink("out/filelink"));
+        assert!(n.is_dir("out/sub"));
+        assert!(!n.exists("out/sub/cycle"));
+    }
+
+    #[test]
+    fn native_read_dir_recursive_flat() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create_dir_all("a/b/c").unwrap();
+        n.create_dir("a/empty").unwrap();
+        for name in &["top", "a/x", "a/b/y", "a/b/c/z"] {
+            n.create(name).unwrap();
+        }
+        let flat = |p: &str| -> Vec<PathBuf> {
+            let mut v: Vec<PathBuf> = n.read_dir_recursive_flat(p).unwrap().map(|e| e.unwrap().path().to_path_buf()).collect();
+            v.sort();
+            v
+        };
+        let mut walked: Vec<PathBuf> = n.walk("a").unwrap().map(|e| e.unwrap())
+            .filter(|e| e.is_file()).map(|e| e.path().to_path_buf()).collect();
+        walked.sort();
+        assert_eq!(flat("a"), walked);
+        assert_eq!(flat("a"), vec!["a/b/c/z", "a/b/y", "a/x"]);
+        assert_eq!(flat("").len(), 4);
+        assert!(flat("a/empty").is_empty());
+        assert!(n.read_dir_recursive_flat("missing").is_err());
+        assert!(n.read_dir_recursive_flat("top").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_read_dir_recursive_flat_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let t = TempDir::new("riotest").unwrap();
+        ::std::fs::create_dir_all(t.path().join("root/d")).unwrap();
+        ::std::fs::File::create(t.path().join("root/d/f")).unwrap();
+        symlink(t.path().join("root/d"), t.path().join("root/linked")).unwrap();
+        symlink(t.path().join("root/d/f"), t.path().join("root/flink")).unwrap();
+        symlink(t.path().join("root"), t.path().join("root/d/cycle")).unwrap();
+        let root = t.path().join("root");
+
+        let n = Native::new(&root);
+        let flat = |fs: &::Counting<Native>| -> Vec<PathBuf> {
+            let mut files: Vec<PathBuf> = fs.read_dir_recursive_flat("").unwrap().map(|e| e.unwrap().path().to_path_buf()).collect();
+            files.sort();
+            files
+        };
+        let mut files: Vec<PathBuf> = n.read_dir_recursive_flat("").unwrap().map(|e| e.unwrap().path().to_path_buf()).collect();
+        files.sort();
+        assert_eq!(files, vec!["d/f", "flink"]);
+        let c = ::Counting::new(Native::new(&root));
+        assert_eq!(flat(&c), files);
+        assert_eq!(c.read_dirs(), 1);
+
+        let strict = Native::builder().follow_symlinks(false).build(&root);
+        let errs = strict.read_dir_recursive_flat("").unwrap().filter(|e| e.is_err()).count();
+        assert_eq!(errs, 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_read_dir_all_invalid_name() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("ok").unwrap();
+        fs::File::create(t.path().join(OsStr::from_bytes(b"bad\xff"))).unwrap();
+        // read_dir skips the bad name, read_dir_all refuses it
+        assert_eq!(n.read_dir("").unwrap().count(), 1);
+        assert_eq!(n.read_dir_all("").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // wrappers keep the all-or-nothing listing
+        let retry = ::Retry::new(::Cached::new(Native::new(t.path())), 1, ::std::time::Duration::from_millis(0));
+        assert_eq!(retry.read_dir_all("").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn native_builder_escape() {
+        let t = TempDir::new("riotest").unwrap();
+        ::std::fs::create_dir(t.path().join("root")).unwrap();
+        ::std::fs::File::create(t.path().join("secret")).unwrap();
+        let n = Native::builder().allow_escape(false).build(t.path().join("root"));
+        n.create_dir("sub").unwrap();
+        n.create("foo").unwrap();
+        for p in &["../secret", "sub/../../secret", "sub/../foo"] {
+            assert_eq!(n.open(p).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+            assert!(n.file_type(p).is_err());
+        }
+        assert!(n.read_dir("sub/..").is_err());
+
+        let n = Native::builder().allow_escape(true).build(t.path().join("root"));
+        assert!(n.open("sub/../foo").is_ok());
+        assert!(n.is_file("../secret"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_builder_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create_dir("target").unwrap();
+        n.create("target/file").unwrap();
+        symlink(t.path().join("target"), t.path().join("link")).unwrap();
+        assert!(n.open("link/file").is_ok());
+        assert!(n.is_dir("link"));
+
+        let n = Native::builder().follow_symlinks(false).build(t.path());
+        assert_eq!(n.open("link/file").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(n.file_type("link").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert!(n.read_dir("link").is_err());
+        assert!(n.open("target/file").is_ok());
+        assert!(!n.exists("link/missing"));
+    }
+
+    #[test]
+    fn native_open_meta() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap().write_all(b"twelve bytes").unwrap();
+        let (mut f, meta) = n.open_meta("foo").unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.len(), 12);
+        assert!(meta.modified().is_some());
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        assert_eq!(s.len() as u64, meta.len());
+        assert_eq!(n.metadata("foo").unwrap().len(), 12);
+        assert!(n.metadata("").unwrap().is_dir());
+        assert!(n.open_meta("missing").is_err());
+
+        let c = ::Counting::new(n);
+        assert_eq!(c.open_meta("foo").unwrap().1.len(), 12);
+        assert_eq!(c.opens(), 1);
+    }
+
+    #[test]
+    fn native_open_read_seek() {
+        use std::io::SeekFrom;
+        use ::ReadSeek;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap().write_all(b"0123456789").unwrap();
+        let mut f: Box<dyn ReadSeek> = n.open_read_seek("foo").unwrap();
+        assert_eq!(f.seek(SeekFrom::Start(6)).unwrap(), 6);
+        let mut buf = [0; 3];
+        f.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"678");
+        assert_eq!(f.seek(SeekFrom::End(-10)).unwrap(), 0);
+        let mut s = String::new();
+        f.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "0123456789");
+        assert!(n.open_read_seek("missing").is_err());
+    }
+
+    #[test]
+    fn native_open_options() {
+        use ::OpenOptions;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        let new = OpenOptions::new().write(true).create_new(true);
+        n.open_options("lock", new).unwrap().write_all(b"held").unwrap();
+        assert_eq!(n.open_options("lock", new).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        n.open_options("lock", OpenOptions::new().append(true)).unwrap().write_all(b"!").unwrap();
+        let mut s = String::new();
+        n.open("lock").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "held!");
+        assert_eq!(n.open_options("missing", OpenOptions::new().write(true)).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    fn native_watch() {
+        use std::time::Duration;
+        use ::{Watch, WatchEvent};
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create_dir("dir").unwrap();
+        n.create("dir/a").unwrap();
+        let events = n.watch("dir").unwrap();
+        n.append("dir/a").unwrap().write_all(b"changed").unwrap();
+        loop {
+            let e = events.recv_timeout(Duration::from_secs(5)).expect("no modify event");
+            if e == WatchEvent::Modify(PathBuf::from("dir/a")) {
+                break;
+            }
+        }
+        n.remove_file("dir/a").unwrap();
+        loop {
+            let e = events.recv_timeout(Duration::from_secs(5)).expect("no remove event");
+            if e == WatchEvent::Remove(PathBuf::from("dir/a")) {
+                break;
+            }
+        }
+    }
+
+    #[cfg(all(feature = "watch", unix))]
+    #[test]
+    fn native_watch_symlinked_prefix() {
+        use std::os::unix::fs::symlink;
+        use std::time::Duration;
+        use ::{Watch, WatchEvent};
+
+        let t = TempDir::new("riotest").unwrap();
+        fs::create_dir(t.path().join("real")).unwrap();
+        symlink(t.path().join("real"), t.path().join("link")).unwrap();
+        let n = Native::new(t.path().join("link"));
+        let events = n.watch("").unwrap();
+        n.create("new").unwrap();
+        loop {
+            let e = events.recv_timeout(Duration::from_secs(5)).expect("no create event");
+            if e == WatchEvent::Create(PathBuf::from("new")) {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn native_copy() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        let data: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+        n.create("src").unwrap().write_all(&data).unwrap();
+        assert_eq!(n.copy("src", "dst").unwrap(), data.len() as u64);
+        let mut v = Vec::new();
+        n.open("dst").unwrap().read_to_end(&mut v).unwrap();
+        assert_eq!(v, data);
+        assert!(n.copy("missing", "dst2").is_err());
+    }
+
+    #[test]
+    fn native_copy_from_mem() {
+        use ::{MemFS, copy_between, copy_tree_between};
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        let m = MemFS::new();
+        m.create_dir_all("a/b").unwrap();
+        m.create("a/one").unwrap().write_all(b"one").unwrap();
+        m.create("a/b/two").unwrap().write_all(b"two!").unwrap();
+
+        assert_eq!(copy_between(&m, "a/one", &n, "one").unwrap(), 3);
+        let mut s = String::new();
+        n.open("one").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "one");
+
+        assert_eq!(copy_tree_between(&m, "a", &n, "copy/a").unwrap(), 7);
+        assert!(n.is_file("copy/a/one"));
+        let mut s = String::new();
+        n.open("copy/a/b/two").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "two!");
+    }
+
+    #[test]
+    fn native_error_path() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        let err = n.open("/a/b").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(err.path(), Some(Path::new("a/b")));
+        assert!(err.to_string().starts_with("failed to open /a/b: "));
+
+        let err = n.read_dir("missing").err().unwrap();
+        assert!(err.to_string().starts_with("failed to read directory missing: "));
+
+        let err: io::Error = n.file_type("gone").err().unwrap().into();
+        assert!(err.to_string().contains("gone"));
+    }
+
+    #[test]
+    fn native_read_dir() {
+        use std::collections::HashSet;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        for name in &["c", "a", "b"] {
+            n.create(name).unwrap();
+        }
+        let mut entries: Vec<_> = n.read_dir("").unwrap().collect();
+        entries.sort_by(|a, b| a.path().as_str().cmp(b.path().as_str()));
+        assert_eq!(entries, vec![n.qualified("a"), n.qualified("b"), n.qualified("c")]);
+        assert_eq!(format!("{:?}", entries[0].clone()), "\"a\"");
+
+        let set: HashSet<_> = n.read_dir("/").unwrap().collect();
+        assert!(set.contains(&n.qualified("b")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_permissions() {
+        use ::Permissions;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap();
+        n.set_permissions("foo", Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(n.permissions("foo").unwrap().mode(), Some(0o600));
+        assert!(!n.permissions("foo").unwrap().readonly());
+        n.set_permissions("foo", Permissions::from_mode(0o444)).unwrap();
+        assert!(n.permissions("foo").unwrap().readonly());
+        n.set_permissions("foo", Permissions::new(false)).unwrap();
+        assert!(!n.permissions("foo").unwrap().readonly());
+    }
+
+    #[test]
+    fn native_escape() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path().join("root"));
+        n.create_dir_all("").unwrap();
+        ::std::fs::File::create(t.path().join("secret")).unwrap();
+
+        let err = n.open("../secret").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        assert!(n.open("../../etc/passwd").is_err());
+        assert!(!n.exists("/../secret"));
+        assert!(n.create("a/./../../x").is_err());
+        assert!(!t.path().join("x").exists());
+    }
+
+    #[test]
+    fn native_hard_link() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap().write_all(b"one").unwrap();
+        n.hard_link("foo", "bar").unwrap();
+        n.append("foo").unwrap().write_all(b"two").unwrap();
+        let mut s = String::new();
+        n.open("bar").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "onetwo");
+        assert!(n.hard_link("missing", "baz").is_err());
+    }
+
+    #[test]
+    fn native_remove() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create_dir("a").unwrap();
+        n.create("a/foo").unwrap();
+        assert!(n.remove_dir("a").is_err());
+        n.remove_file("a/foo").unwrap();
+        n.remove_dir("a").unwrap();
+        assert!(!n.exists("a"));
+        assert!(n.remove_file("a/foo").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn native_canonicalize() {
+        use std::os::unix::fs::symlink;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path().join("root"));
+        fs::create_dir_all(t.path().join("root/real")).unwrap();
+        n.create("real/foo").unwrap();
+        symlink(t.path().join("root/real"), t.path().join("root/link")).unwrap();
+        symlink(t.path(), t.path().join("root/out")).unwrap();
+
+        assert_eq!(n.canonicalize("link/foo").unwrap(), "real/foo");
+        assert_eq!(n.canonicalize("real/./../link/foo").unwrap(), "real/foo");
+        assert!(n.canonicalize("").unwrap().is_root());
+        assert!(n.canonicalize("out").is_err());
+        assert!(n.canonicalize("out/root/real").is_ok());
+        assert!(n.canonicalize("..").is_err());
+        assert!(n.canonicalize("link/missing").is_err());
+
+        // outside files are refused the same way whether they exist or not
+        fs::File::create(t.path().join("secret")).unwrap();
+        assert_eq!(n.canonicalize("../secret").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(n.canonicalize("../missing").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(n.canonicalize("real/../../secret").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+
+        let strict = Native::builder().follow_symlinks(false).build(t.path().join("root"));
+        assert_eq!(strict.canonicalize("real/./foo").unwrap(), "real/foo");
+        assert_eq!(strict.canonicalize("link/foo").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn native_rename_create() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap().write_all(b"test").unwrap();
+        assert!(n.rename("foo", "a/b/foo").is_err());
+        n.rename_create("foo", "a/b/foo").unwrap();
+        assert!(!n.exists("foo"));
+        let mut s = String::new();
+        n.open("a/b/foo").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "test");
+    }
+
+    #[test]
+    fn native_truncate() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap().write_all(b"0123456789").unwrap();
+        n.truncate("foo", 4).unwrap();
+        let mut v = Vec::new();
+        n.open("foo").unwrap().read_to_end(&mut v).unwrap();
+        assert_eq!(v, b"0123");
+        n.truncate("foo", 6).unwrap();
+        let mut v = Vec::new();
+        n.open("foo").unwrap().read_to_end(&mut v).unwrap();
+        assert_eq!(v, b"0123\0\0");
+        assert!(n.truncate("missing", 0).is_err());
+    }
+
+    #[test]
+    fn native_files_dirs() {
+        use ::DirEntriesExt;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("f1").unwrap();
+        n.create("f2").unwrap();
+        n.create_dir("d1").unwrap();
+        let mut files: Vec<String> = n.read_dir("").unwrap().files().map(|p| p.path().as_str().to_string()).collect();
+        files.sort();
+        assert_eq!(files, vec!["f1", "f2"]);
+        let dirs: Vec<_> = n.read_dir("").unwrap().dirs().collect();
+        assert_eq!(dirs, vec![n.qualified("d1")]);
+    }
+
+    #[test]
+    fn native_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Arc::new(Native::new(t.path()));
+        n.create("one").unwrap().write_all(b"one").unwrap();
+        n.create("two").unwrap().write_all(b"two").unwrap();
+
+        let handles: Vec<_> = vec!["one", "two"].into_iter().map(|name| {
+            let n = n.clone();
+            thread::spawn(move || {
+                let mut s = String::new();
+                n.open(name).unwrap().read_to_string(&mut s).unwrap();
+                assert_eq!(n.read_dir("").unwrap().count(), 2);
+                s
+            })
+        }).collect();
+        let results: Vec<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec!["one", "two"]);
+    }
+
+    #[test]
+    fn native_path() {
+        let n = Native::new("/prefix");
+        let expected = path::Path::new("/prefix").join("a").join("b");
+        assert_eq!(n.path("a/b"), expected);
+        assert_eq!(n.path("/a//b/"), expected);
+        assert_eq!(n.path(""), path::Path::new("/prefix"));
+
+        let mut buf = path::PathBuf::from("/leftover/junk");
+        n.path_into("/a/b", &mut buf);
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn native_open_buffered() {
+        use std::io::BufRead;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("lines").unwrap().write_all(b"one\ntwo\nthree\n").unwrap();
+        let lines: Vec<String> = n.open_buffered("lines").unwrap().lines().map(|l| l.unwrap()).collect();
+        assert_eq!(lines, vec!["one", "two", "three"]);
+    }
+
+    #[test]
+    fn native_create_buffered() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        let mut expected = Vec::new();
+        {
+            let mut f = n.create_buffered("records").unwrap();
+            for i in 0..1000 {
+                let record = format!("{},", i);
+                f.write_all(record.as_bytes()).unwrap();
+                expected.extend_from_slice(record.as_bytes());
+            }
+            f.flush().unwrap();
+        }
+        let mut v = Vec::new();
+        n.open("records").unwrap().read_to_end(&mut v).unwrap();
+        assert_eq!(v, expected);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn native_mmap() {
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("big").unwrap().write_all(&[7; 4096]).unwrap();
+        let m = n.open_mmap("big").unwrap();
+        assert_eq!(&m[..], &[7; 4096][..]);
+        let mut v = Vec::new();
+        n.open_mmap("big").unwrap().read_to_end(&mut v).unwrap();
+        assert_eq!(v, vec![7; 4096]);
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn native_mmap_seek() {
+        use std::io::SeekFrom;
+        use ::ReadSeek;
+
+        let t = TempDir::new("riotest").unwrap();
+        let n = Native::new(t.path());
+        n.create("foo").unwrap().write_all(b"0123456789").unwrap();
+        let mut m: Box<dyn ReadSeek> = Box::new(n.open_mmap("foo").unwrap());
+        assert_eq!(m.seek(SeekFrom::End(-3)).unwrap(), 7);
+        let mut s = String::new();
+        m.read_to_string(&mut s).unwrap();
+        assert_eq!(s, "789");
+        assert_eq!(m.seek(SeekFrom::Current(-5)).unwrap(), 5);
+        let mut buf = [0; 2];
+        m.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"56");
+        assert!(m.seek(SeekFrom::Current(-8)).is_err());
+        assert_eq!(m.seek(SeekFrom::Start(20)).unwrap(), 20);
+        assert_eq!(m.read(&mut buf).unwrap(), 0);
+    }
 }
@@ -0,0 +1,69 @@
+use std::{io, iter};
+use path::Path;
+use fs::{FSRead, FSWrite, Error, Result, FileType, QPath};
+
+/// A filesystem that discards everything written to it.
+///
+/// Like `/dev/null`, writes always succeed and go nowhere, and nothing
+/// ever exists to be read. This is handy for measuring the cost of a
+/// pipeline without doing any IO.
+pub struct Null;
+
+fn not_found<P: AsRef<Path>>(op: &'static str, path: P) -> Error {
+    Error::new(op, path, io::Error::new(io::ErrorKind::NotFound, "File not found."))
+}
+
+impl<'a> FSRead<'a> for Null {
+    type ReadFile = io::Empty;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Empty> {
+        Err(not_found("open", path))
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        Err(not_found("stat", path))
+    }
+
+    type ReadDir = iter::Empty<QPath<'a, Null>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        Err(not_found("read directory", path))
+    }
+}
+
+impl<'a> FSWrite<'a> for Null {
+    type WriteFile = io::Sink;
+
+    fn create<P: AsRef<Path>>(&self, _path: P) -> Result<io::Sink> {
+        Ok(io::sink())
+    }
+
+    fn append<P: AsRef<Path>>(&self, _path: P) -> Result<io::Sink> {
+        Ok(io::sink())
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, _path: P, _len: u64) -> Result<()> {
+        Ok(())
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, _path: P) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite};
+    use std::io::Read;
+
+    #[test]
+    fn null_discards() {
+        let mut f = Null.create("big").unwrap();
+        let n = io::copy(&mut io::repeat(1).take(1 << 20), &mut f).unwrap();
+        assert_eq!(n, 1 << 20);
+        assert!(!Null.exists("big"));
+        assert!(Null.open("big").is_err());
+        assert!(Null.read_dir("").is_err());
+    }
+}
@@ -3,16 +3,25 @@
 // except all paths use / as seperator, and no paths are relative. Yes, really.
 // ("/a/b" is the same as "a/b", Path is essentially isomorphic to &[&str])
 
-use std::{mem, fmt};
-use std::ops::{Deref};
+use std::{cmp, mem, fmt, vec};
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::hash::{Hash, Hasher};
+use std::ops::{Bound, Deref, Div, RangeBounds};
 use std::borrow::{Borrow, ToOwned, Cow};
 
+// short paths are stored inline when smartstring is available
+#[cfg(feature = "smartstring")]
+type Inner = ::smartstring::alias::String;
+#[cfg(not(feature = "smartstring"))]
+type Inner = String;
+
 /// An owned path string.
 ///
 /// See [Path](struct.Path.html) for details.
 #[derive(Clone)]
 pub struct PathBuf {
-    inner: String
+    inner: Inner
 }
 
 /// A path reference.
@@ -36,6 +45,78 @@ pub struct Components<'a> {
     j: usize,
 }
 
+/// An iterator over components of a path, as string slices.
+///
+/// This is produced by [Path::iter()](struct.Path.html#method.iter).
+#[derive(Clone)]
+pub struct Iter<'a> {
+    inner: Components<'a>,
+}
+
+/// An iterator over components of a path with their byte ranges.
+///
+/// This is produced by [Components::indices()](struct.Components.html#method.indices).
+#[derive(Clone)]
+pub struct Indices<'a> {
+    inner: Components<'a>,
+}
+
+/// A range of paths, for `BTreeMap::range` on maps keyed by `PathBuf`.
+///
+/// `Path` is unsized, so a `Range<&Path>` can't be used for this. This
+/// is produced by [Path::descendant_range()](struct.Path.html#method.descendant_range),
+/// and a tuple of `Bound<&Path>` works for other ranges.
+#[derive(Clone, Debug)]
+pub struct PathRange {
+    start: Bound<PathBuf>,
+    end: Bound<PathBuf>,
+}
+
+/// An owning iterator over the components of a path.
+///
+/// This is produced by calling `into_iter()` on a `PathBuf`.
+pub struct IntoIter {
+    inner: vec::IntoIter<String>,
+}
+
+/// A builder for editing the components of a path by index.
+///
+/// This is produced by [Path::edit()](struct.Path.html#method.edit).
+/// Like the `Vec` methods they mirror, each edit panics if its index
+/// is out of range.
+#[derive(Clone, Debug)]
+pub struct PathBuilder {
+    parts: Vec<String>,
+}
+
+impl PathBuilder {
+    /// Replace the component at `index` with all the components of `path`.
+    pub fn replace<P: AsRef<Path>>(mut self, index: usize, path: P) -> PathBuilder {
+        self.parts.remove(index);
+        self.insert(index, path)
+    }
+
+    /// Insert all the components of `path` before `index`.
+    pub fn insert<P: AsRef<Path>>(mut self, index: usize, path: P) -> PathBuilder {
+        assert!(index <= self.parts.len(), "insertion index {} out of range", index);
+        let tail = self.parts.split_off(index);
+        self.parts.extend(path.as_ref().iter().map(String::from));
+        self.parts.extend(tail);
+        self
+    }
+
+    /// Remove the component at `index`.
+    pub fn remove(mut self, index: usize) -> PathBuilder {
+        self.parts.remove(index);
+        self
+    }
+
+    /// Finish editing, and produce the new path.
+    pub fn build(self) -> PathBuf {
+        PathBuf::from(self.parts.join("/"))
+    }
+}
+
 impl<'a> Components<'a> {
     fn trim_left(&mut self) -> usize {
         while self.i < self.j && self.path[self.i] == b'/' {
@@ -60,6 +141,37 @@ impl<'a> Components<'a> {
         unsafe { Path::from_u8_slice(&self.path[self.i..self.j]) }
     }
 
+    /// The unconsumed part of the path, as a string slice.
+    pub fn as_str(&self) -> &'a str {
+        self.as_path().as_str()
+    }
+
+    /// The next component, without advancing the iterator.
+    pub fn peek(&self) -> Option<&'a Path> {
+        self.clone().next()
+    }
+
+    /// The last remaining component, without advancing the iterator.
+    pub fn peek_back(&self) -> Option<&'a Path> {
+        self.clone().next_back()
+    }
+
+    /// Count the remaining components without consuming them.
+    pub fn len(&self) -> usize {
+        self.clone().count()
+    }
+
+    /// Whether there are no components left.
+    pub fn is_empty(&self) -> bool {
+        self.peek().is_none()
+    }
+
+    /// Yield each remaining component along with the start and end of
+    /// its byte range in the original path's `as_str()`.
+    pub fn indices(self) -> Indices<'a> {
+        Indices { inner: self }
+    }
+
     // FIXME other component stuff
 }
 
@@ -79,6 +191,19 @@ impl<'a> Iterator for Components<'a> {
             Some(unsafe { Path::from_u8_slice(&self.path[start..end]) })
         }
     }
+
+    fn nth(&mut self, n: usize) -> Option<&'a Path> {
+        // skip over components without slicing them out
+        for _ in 0..n {
+            if self.trim_left() == self.j {
+                return None;
+            }
+            while self.i < self.j && self.path[self.i] != b'/' {
+                self.i += 1;
+            }
+        }
+        self.next()
+    }
 }
 
 impl<'a> DoubleEndedIterator for Components<'a> {
@@ -97,30 +222,170 @@ impl<'a> DoubleEndedIterator for Components<'a> {
     }
 }
 
-impl PathBuf {
-    fn as_mut_vec(&mut self) -> &mut Vec<u8> {
-        unsafe { &mut *(self as *mut PathBuf as *mut Vec<u8>) }
+impl<'a> Indices<'a> {
+    fn span(&self, part: &'a Path) -> (usize, usize, &'a Path) {
+        // components are subslices, so their offsets come from pointers
+        let start = part.inner.as_ptr() as usize - self.inner.path.as_ptr() as usize;
+        (start, start + part.inner.len(), part)
     }
-    
+}
+
+impl<'a> Iterator for Indices<'a> {
+    type Item = (usize, usize, &'a Path);
+
+    fn next(&mut self) -> Option<(usize, usize, &'a Path)> {
+        self.inner.next().map(|p| self.span(p))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Indices<'a> {
+    fn next_back(&mut self) -> Option<(usize, usize, &'a Path)> {
+        self.inner.next_back().map(|p| self.span(p))
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.inner.next().map(Path::as_str)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<&'a str> {
+        self.inner.nth(n).map(Path::as_str)
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<&'a str> {
+        self.inner.next_back().map(Path::as_str)
+    }
+}
+
+impl PathBuf {
     pub fn new() -> PathBuf {
-        PathBuf { inner: String::new() }
+        PathBuf { inner: Inner::new() }
     }
 
     pub fn as_path(&self) -> &Path {
         self
     }
 
-    // FIXME all following methods
+    /// Empty this path, keeping its buffer for reuse.
+    pub fn clear(&mut self) {
+        // unlike clear, this keeps a smartstring on the heap
+        self.inner.truncate(0);
+    }
+
+    /// The number of bytes this path can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Reserve room for at least `additional` more bytes.
+    ///
+    /// With the `smartstring` feature, this moves a short path out of
+    /// inline storage onto the heap if it needs more room.
+    pub fn reserve(&mut self, additional: usize) {
+        #[cfg(not(feature = "smartstring"))]
+        self.inner.reserve(additional);
+        #[cfg(feature = "smartstring")]
+        {
+            use smartstring::MAX_INLINE;
+
+            let len = self.inner.len();
+            if len + additional > self.inner.capacity() {
+                // smartstring only boxes strings too long to inline, but
+                // keeps them boxed as they shrink, so pad and cut back
+                let mut s = String::with_capacity(cmp::max(len + additional, MAX_INLINE + 1));
+                s.push_str(&self.inner);
+                while s.len() <= MAX_INLINE {
+                    s.push('\0');
+                }
+                let mut inner = Inner::from(s);
+                inner.truncate(len);
+                self.inner = inner;
+            }
+        }
+    }
+
+    /// Add the components of `path` to the end of this path.
+    ///
+    /// Only components are copied, so pushing "" or "/" does nothing,
+    /// and the result never gains doubled or trailing separators.
     pub fn push<P: AsRef<Path>>(&mut self, path: P) {
-        if let Some(&b'/') = self.as_mut_vec().last() {
-            // last path ends with seperator
-        } else if let Some(&b'/') = path.as_ref().as_u8_slice().first() {
-            // next path starts with seperator
-        } else {
-            // add a seperator
-            self.inner.push('/');
+        for part in path.as_ref().iter() {
+            // reuse a separator already on the end
+            if !self.inner.is_empty() && !self.inner.ends_with('/') {
+                self.inner.push('/');
+            }
+            self.inner.push_str(part);
+        }
+    }
+
+    /// Convert an operating system path, or `None` if it isn't valid
+    /// UTF-8 or starts with a prefix such as a Windows drive letter.
+    ///
+    /// A leading root is dropped, since all paths here are relative.
+    /// Unlike the lossy `From<std::path::PathBuf>`, "." and ".." are
+    /// kept as components.
+    pub fn from_os_path(path: &::std::path::Path) -> Option<PathBuf> {
+        use std::path::Component;
+
+        let mut p = PathBuf::new();
+        for c in path.components() {
+            match c {
+                Component::Prefix(_) => return None,
+                Component::RootDir => {}
+                _ => p.push(c.as_os_str().to_str()?),
+            }
+        }
+        Some(p)
+    }
+
+    /// Build a path from a possibly messy string, in normal form.
+    ///
+    /// Repeated, leading and trailing slashes are dropped, "." is
+    /// removed and ".." removes the component before it. A ".." with
+    /// nothing left to remove is dropped, so the result never climbs
+    /// out of the root and is always safe.
+    pub fn from_normalized<S: AsRef<str>>(s: S) -> PathBuf {
+        let mut parts: Vec<&str> = Vec::new();
+        for part in Path::new(s.as_ref()) {
+            match part.as_str() {
+                "." => {}
+                ".." => {
+                    parts.pop();
+                }
+                part => parts.push(part),
+            }
         }
-        self.inner.push_str(path.as_ref().as_ref());
+        PathBuf::from(parts.join("/"))
+    }
+
+    /// Add the components of `path` to the front of this path.
+    ///
+    /// This mirrors `push`: only components are copied, so prepending
+    /// "" or "/" does nothing, and the result never gains doubled or
+    /// leading separators.
+    pub fn prepend<P: AsRef<Path>>(&mut self, path: P) {
+        let mut front = Inner::new();
+        for part in path.as_ref().iter() {
+            if !front.is_empty() {
+                front.push('/');
+            }
+            front.push_str(part);
+        }
+        if front.is_empty() {
+            return;
+        }
+        // a separator already on the front is replaced
+        let rest = self.inner.trim_start_matches('/');
+        if !rest.is_empty() {
+            front.push('/');
+            front.push_str(rest);
+        }
+        self.inner = front;
     }
 }
 
@@ -143,8 +408,30 @@ impl Path {
         &self.inner
     }
 
+    /// The path as a string with no leading, trailing or repeated
+    /// slashes, so equal paths always give the same string.
+    ///
+    /// This only allocates if the path isn't in that form already.
+    pub fn normalized_str(&self) -> Cow<'_, str> {
+        let s = self.as_str();
+        if s.starts_with('/') || s.ends_with('/') || s.contains("//") {
+            Cow::Owned(self.iter().collect::<Vec<_>>().join("/"))
+        } else {
+            Cow::Borrowed(s)
+        }
+    }
+
     pub fn to_path_buf(&self) -> PathBuf {
-        PathBuf::from(self.inner.to_string())
+        PathBuf { inner: Inner::from(&self.inner) }
+    }
+
+    /// Convert to a relative operating system path, joining the
+    /// components with the OS separator.
+    ///
+    /// This is only a change of representation; `Native` resolves
+    /// paths against its root instead.
+    pub fn to_os_path(&self) -> ::std::path::PathBuf {
+        self.iter().collect()
     }
 
     pub fn parent(&self) -> Option<&Path> {
@@ -156,16 +443,44 @@ impl Path {
         }
     }
 
+    /// Split off the first component, returning it and the rest.
+    pub fn split_first(&self) -> Option<(&str, &Path)> {
+        let mut comps = self.components();
+        comps.next().map(|first| (first.as_str(), comps.as_path()))
+    }
+
+    /// Split off the last component, returning it and the parent.
+    pub fn split_last(&self) -> Option<(&str, &Path)> {
+        let mut comps = self.components();
+        comps.next_back().map(|last| (last.as_str(), comps.as_path()))
+    }
+
     pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
         let mut owned = self.to_owned();
         owned.push(path);
         owned
     }
 
+    /// Join each of `parts` onto this path in turn.
+    pub fn join_all<I: IntoIterator<Item=P>, P: AsRef<Path>>(&self, parts: I) -> PathBuf {
+        let mut owned = self.to_owned();
+        for part in parts {
+            owned.push(part);
+        }
+        owned
+    }
+
     pub fn file_name(&self) -> Option<&str> {
         self.components().next_back().map(|p| p.as_ref())
     }
 
+    /// Whether the file name starts with a dot, as in ".git".
+    ///
+    /// Only the last component counts, so "a/.b/c" is not hidden.
+    pub fn is_hidden(&self) -> bool {
+        self.file_name().is_some_and(|n| n.starts_with('.'))
+    }
+
     pub fn extension(&self) -> Option<&str> {
         self.file_name().and_then(|fname| {
             let mut s = fname.rsplit('.');
@@ -179,20 +494,284 @@ impl Path {
         })
     }
 
+    /// The file name up to its first dot, so "archive.tar.gz" gives
+    /// "archive".
+    ///
+    /// A leading dot is part of the name rather than a separator, like
+    /// in `std`, so ".env" gives ".env" and ".env.local" gives ".env".
+    pub fn file_prefix(&self) -> Option<&str> {
+        self.file_name().map(|fname| {
+            let skip = if fname.starts_with('.') { 1 } else { 0 };
+            let end = fname[skip..].find('.').map_or(fname.len(), |i| i + skip);
+            &fname[..end]
+        })
+    }
+
+    /// Whether the file name has the extension `ext`, compared
+    /// exactly.
+    pub fn has_extension<S: AsRef<str>>(&self, ext: S) -> bool {
+        self.extension() == Some(ext.as_ref())
+    }
+
+    /// Like `has_extension`, but ignoring ASCII case.
+    pub fn has_extension_ignore_case<S: AsRef<str>>(&self, ext: S) -> bool {
+        self.extension().is_some_and(|e| e.eq_ignore_ascii_case(ext.as_ref()))
+    }
+
+    /// Compare two paths by components, ignoring ASCII case.
+    pub fn eq_ignore_case<P: AsRef<Path>>(&self, other: P) -> bool {
+        let mut a = self.iter();
+        let mut b = other.as_ref().iter();
+        loop {
+            match (a.next(), b.next()) {
+                (Some(x), Some(y)) if x.eq_ignore_ascii_case(y) => {}
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+
+    /// A copy of this path with all ASCII letters lowercased.
+    pub fn to_ascii_lowercase(&self) -> PathBuf {
+        PathBuf::from(self.inner.to_ascii_lowercase())
+    }
+
+    /// Start editing a copy of this path by component index.
+    ///
+    /// For example, `Path::new("a/b/c").edit().replace(0, "x").build()`
+    /// is "x/b/c".
+    pub fn edit(&self) -> PathBuilder {
+        PathBuilder { parts: self.iter().map(String::from).collect() }
+    }
+
+    /// Build a new path by applying `f` to each component in turn.
+    pub fn map_components<F: FnMut(&Path) -> PathBuf>(&self, mut f: F) -> PathBuf {
+        let mut out = PathBuf::new();
+        for c in self.components() {
+            out.push(f(c));
+        }
+        out
+    }
+
+    /// The longest run of leading components shared with `other`.
+    pub fn common_prefix<P: AsRef<Path>>(&self, other: P) -> PathBuf {
+        let mut prefix = PathBuf::new();
+        for (a, b) in self.iter().zip(other.as_ref().iter()) {
+            if a != b {
+                break;
+            }
+            prefix.push(a);
+        }
+        prefix
+    }
+
+    /// Whether `needle`'s components appear as a contiguous run
+    /// anywhere in this path.
+    ///
+    /// For example, "a/b/c/d" contains "b/c" but not "a/c". An empty
+    /// needle is contained in every path.
+    pub fn contains<P: AsRef<Path>>(&self, needle: P) -> bool {
+        let needle: Vec<&str> = needle.as_ref().iter().collect();
+        if needle.is_empty() {
+            return true;
+        }
+        let parts: Vec<&str> = self.iter().collect();
+        parts.windows(needle.len()).any(|w| w == &needle[..])
+    }
+
+    /// The rest of this path, if `base`'s components are a prefix of
+    /// its components.
+    ///
+    /// For example, "/a/b/c" with the prefix "a" leaves "b/c".
+    pub fn strip_prefix<P: AsRef<Path>>(&self, base: P) -> Option<&Path> {
+        let mut rest = self.components();
+        for part in base.as_ref().iter() {
+            if rest.next().map(Path::as_str) != Some(part) {
+                return None;
+            }
+        }
+        Some(Path::new(rest.as_str().trim_start_matches('/')))
+    }
+
+    /// The start of this path, if `suffix`'s components are a suffix
+    /// of its components.
+    ///
+    /// For example, "a/b/c/d" without the suffix "c/d" leaves "a/b".
+    pub fn strip_suffix<P: AsRef<Path>>(&self, suffix: P) -> Option<&Path> {
+        let mut rest = self.components();
+        for part in suffix.as_ref().iter().rev() {
+            if rest.next_back().map(Path::as_str) != Some(part) {
+                return None;
+            }
+        }
+        Some(Path::new(rest.as_str().trim_end_matches('/')))
+    }
+
+    /// Whether `base`'s components are a prefix of this path's
+    /// components, so "a/bc" does not start with "a/b".
+    pub fn starts_with<P: AsRef<Path>>(&self, base: P) -> bool {
+        self.strip_prefix(base).is_some()
+    }
+
+    /// Whether `suffix`'s components are a suffix of this path's
+    /// components.
+    pub fn ends_with<P: AsRef<Path>>(&self, suffix: P) -> bool {
+        self.strip_suffix(suffix).is_some()
+    }
+
+    /// Show this path with `base` trimmed off the front, if it is a
+    /// prefix, or in full otherwise. The path itself is not changed.
+    pub fn display_relative<'a, P: AsRef<Path>>(&'a self, base: P) -> impl fmt::Display + 'a {
+        Display { path: self.strip_prefix(base).unwrap_or(self) }
+    }
+
+    /// Express this path relative to `base`, using ".." to climb out
+    /// of `base` where needed.
+    ///
+    /// For example, "a/b/c" relative to "a/x" is "../b/c".
+    pub fn relative_to<P: AsRef<Path>>(&self, base: P) -> PathBuf {
+        let base = base.as_ref();
+        let shared = self.iter().zip(base.iter()).take_while(|&(a, b)| a == b).count();
+        let mut rel = PathBuf::new();
+        for _ in base.iter().skip(shared) {
+            rel.push("..");
+        }
+        for part in self.iter().skip(shared) {
+            rel.push(part);
+        }
+        rel
+    }
+
     pub fn components(&self) -> Components {
         Components { path: self.as_u8_slice(), i: 0, j: self.inner.len() }
     }
+
+    pub fn iter(&self) -> Iter {
+        Iter { inner: self.components() }
+    }
+
+    /// Whether this path has no components, like "" or "/".
+    pub fn is_empty(&self) -> bool {
+        self.components().next().is_none()
+    }
+
+    /// Whether this path is the root. Since all paths are relative,
+    /// this is the same as `is_empty`.
+    pub fn is_root(&self) -> bool {
+        self.is_empty()
+    }
+
+    /// Whether this path is free of "." and ".." components.
+    ///
+    /// Paths here have no special meaning for these, but operating
+    /// system paths do. Backends that pass paths through to the OS
+    /// should refuse unsafe paths so they can't escape their root.
+    pub fn is_safe(&self) -> bool {
+        self.iter().all(|c| c != "." && c != "..")
+    }
+
+    /// The range of paths strictly under this one.
+    ///
+    /// Paths sort by components, so everything under a directory sorts
+    /// together straight after it, and this range picks out exactly
+    /// those paths from a `BTreeMap` keyed by `PathBuf`.
+    pub fn descendant_range(&self) -> PathRange {
+        let end = match self.split_last() {
+            // the next possible name after the last component
+            Some((last, parent)) => Bound::Excluded(parent.join(format!("{}\0", last))),
+            None => Bound::Unbounded,
+        };
+        PathRange { start: Bound::Excluded(self.to_path_buf()), end: end }
+    }
+
+    /// The number of components in this path.
+    pub fn component_count(&self) -> usize {
+        self.components().len()
+    }
+
+    /// The part of this path covering a range of component indices.
+    ///
+    /// For example, "a/b/c/d" sliced with `1..3` is "b/c". The result
+    /// borrows from this path. Unlike slice indexing this never panics:
+    /// indices past the end are clamped to the component count, and a
+    /// range that ends before it starts gives the empty path.
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> &Path {
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => Some(n.saturating_add(1)),
+            Bound::Excluded(&n) => Some(n),
+            Bound::Unbounded => None,
+        };
+        let mut c = self.components();
+        for _ in 0..start {
+            if c.next().is_none() {
+                break;
+            }
+        }
+        let from = c.trim_left();
+        let to = match end {
+            Some(end) => {
+                let mut to = from;
+                for _ in start..end {
+                    match c.next() {
+                        // components are subslices, so their end is an offset into self
+                        Some(p) => to = p.inner.as_ptr() as usize - self.inner.as_ptr() as usize + p.inner.len(),
+                        None => break,
+                    }
+                }
+                to
+            }
+            None => c.trim_right(),
+        };
+        Path::new(&self.inner[from..to])
+    }
+}
+
+impl RangeBounds<Path> for PathRange {
+    fn start_bound(&self) -> Bound<&Path> {
+        match self.start {
+            Bound::Included(ref p) => Bound::Included(p),
+            Bound::Excluded(ref p) => Bound::Excluded(p),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
+
+    fn end_bound(&self) -> Bound<&Path> {
+        match self.end {
+            Bound::Included(ref p) => Bound::Included(p),
+            Bound::Excluded(ref p) => Bound::Excluded(p),
+            Bound::Unbounded => Bound::Unbounded,
+        }
+    }
 }
 
 impl<'a, T: ?Sized + AsRef<str>> From<&'a T> for PathBuf {
     fn from(s: &'a T) -> PathBuf {
-        PathBuf::from(s.as_ref().to_string())
+        PathBuf { inner: Inner::from(s.as_ref()) }
     }
 }
 
 impl From<String> for PathBuf {
     fn from(s: String) -> PathBuf {
-        PathBuf { inner: s }
+        PathBuf { inner: Inner::from(s) }
+    }
+}
+
+impl FromStr for PathBuf {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<PathBuf, Infallible> {
+        Ok(PathBuf::from(s))
+    }
+}
+
+impl Default for PathBuf {
+    fn default() -> PathBuf {
+        PathBuf::new()
     }
 }
 
@@ -224,6 +803,32 @@ impl<'a> From<&'a Path> for Cow<'a, Path> {
     }
 }
 
+// path / "segment" joins, like join and push
+impl<P: AsRef<Path>> Div<P> for &Path {
+    type Output = PathBuf;
+
+    fn div(self, rhs: P) -> PathBuf {
+        self.join(rhs)
+    }
+}
+
+impl<P: AsRef<Path>> Div<P> for &PathBuf {
+    type Output = PathBuf;
+
+    fn div(self, rhs: P) -> PathBuf {
+        self.join(rhs)
+    }
+}
+
+impl<P: AsRef<Path>> Div<P> for PathBuf {
+    type Output = PathBuf;
+
+    fn div(mut self, rhs: P) -> PathBuf {
+        self.push(rhs);
+        self
+    }
+}
+
 impl<'a> From<PathBuf> for Cow<'a, Path> {
     #[inline]
     fn from(s: PathBuf) -> Cow<'a, Path> {
@@ -238,21 +843,42 @@ impl ToOwned for Path {
     }
 }
 
-// by components:
-// cmp::PartialEq
-// Hash
-// cmp::PartialOrd
-// cmp::Ord
+impl PartialEq for PathBuf {
+    fn eq(&self, other: &PathBuf) -> bool {
+        self.as_path() == other.as_path()
+    }
+}
+
+impl Eq for PathBuf {}
+
+// must agree with Path, so maps keyed by PathBuf can be queried by &Path
+impl Hash for PathBuf {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.as_path().hash(h)
+    }
+}
+
+impl PartialOrd for PathBuf {
+    fn partial_cmp(&self, other: &PathBuf) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PathBuf {
+    fn cmp(&self, other: &PathBuf) -> cmp::Ordering {
+        self.as_path().cmp(other.as_path())
+    }
+}
 
 impl AsRef<str> for PathBuf {
     fn as_ref(&self) -> &str {
-        &self.inner[..]
+        self.inner.as_str()
     }
 }
 
 impl Into<String> for PathBuf {
     fn into(self) -> String {
-        self.inner
+        self.inner.into()
     }
 }
 
@@ -274,18 +900,61 @@ impl From<::std::path::PathBuf> for PathBuf {
     }
 }
 
+// shows a path's components joined by "/", for display_relative
+struct Display<'a> {
+    path: &'a Path,
+}
+
+impl<'a> fmt::Display for Display<'a> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for (i, part) in self.path.iter().enumerate() {
+            if i > 0 {
+                formatter.write_str("/")?;
+            }
+            formatter.write_str(part)?;
+        }
+        Ok(())
+    }
+}
+
 impl fmt::Debug for Path {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         self.inner.fmt(formatter)
     }
 }
 
-// by components:
-// cmp::PartialEq
-// Hash
-// cmp::Eq
-// cmp::PartialOrd
-// cmp::Ord
+impl PartialEq for Path {
+    fn eq(&self, other: &Path) -> bool {
+        // identical strings always have identical components
+        self.inner == other.inner || self.components().map(Path::as_str).eq(other.components().map(Path::as_str))
+    }
+}
+
+impl Eq for Path {}
+
+// the normalized string is the components joined by "/", and that
+// only needs building for paths with extra slashes
+impl Hash for Path {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.normalized_str().hash(h)
+    }
+}
+
+impl PartialOrd for Path {
+    fn partial_cmp(&self, other: &Path) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// by components, so "a/b" sorts before "a-b" like it would in a tree.
+// PathBuf orders the same way, so maps keyed by PathBuf can be
+// queried with &Path bounds, and everything under a directory sorts
+// together straight after it (see descendant_range).
+impl Ord for Path {
+    fn cmp(&self, other: &Path) -> cmp::Ordering {
+        self.components().map(Path::as_str).cmp(other.components().map(Path::as_str))
+    }
+}
 
 impl AsRef<str> for Path {
     fn as_ref(&self) -> &str {
@@ -317,6 +986,33 @@ impl AsRef<Path> for PathBuf {
     }
 }
 
+impl Iterator for IntoIter {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for IntoIter {
+    fn next_back(&mut self) -> Option<String> {
+        self.inner.next_back()
+    }
+}
+
+impl IntoIterator for PathBuf {
+    type Item = String;
+    type IntoIter = IntoIter;
+    fn into_iter(self) -> IntoIter {
+        let parts: Vec<String> = self.iter().map(String::from).collect();
+        IntoIter { inner: parts.into_iter() }
+    }
+}
+
 impl<'a> IntoIterator for &'a PathBuf {
     type Item = &'a Path;
     type IntoIter = Components<'a>;
@@ -333,12 +1029,52 @@ impl<'a> IntoIterator for &'a Path {
     }
 }
 
-// partialeq for
-// PathBuf, Path
-// PathBuf, &'a Path
-// Cow<'a, Path>, Path
-// Cow<'a, Path>, &'b Path
-// Cow<'a, Path>, PathBuf
+// compares and orders two path-like types by components, in both directions
+macro_rules! impl_cmp {
+    (<$($life:lifetime),*> $lhs:ty, $rhs:ty) => {
+        impl<$($life),*> PartialEq<$rhs> for $lhs {
+            #[inline]
+            fn eq(&self, other: &$rhs) -> bool {
+                <Path as PartialEq>::eq(self.as_ref(), other.as_ref())
+            }
+        }
+
+        impl<$($life),*> PartialEq<$lhs> for $rhs {
+            #[inline]
+            fn eq(&self, other: &$lhs) -> bool {
+                <Path as PartialEq>::eq(self.as_ref(), other.as_ref())
+            }
+        }
+
+        impl<$($life),*> PartialOrd<$rhs> for $lhs {
+            #[inline]
+            fn partial_cmp(&self, other: &$rhs) -> Option<cmp::Ordering> {
+                <Path as PartialOrd>::partial_cmp(self.as_ref(), other.as_ref())
+            }
+        }
+
+        impl<$($life),*> PartialOrd<$lhs> for $rhs {
+            #[inline]
+            fn partial_cmp(&self, other: &$lhs) -> Option<cmp::Ordering> {
+                <Path as PartialOrd>::partial_cmp(self.as_ref(), other.as_ref())
+            }
+        }
+    }
+}
+
+impl_cmp!(<> PathBuf, Path);
+impl_cmp!(<'a> PathBuf, &'a Path);
+impl_cmp!(<> Path, str);
+impl_cmp!(<'a> Path, &'a str);
+impl_cmp!(<> Path, String);
+impl_cmp!(<'a> &'a Path, str);
+impl_cmp!(<'a> &'a Path, String);
+impl_cmp!(<> PathBuf, str);
+impl_cmp!(<'a> PathBuf, &'a str);
+impl_cmp!(<> PathBuf, String);
+impl_cmp!(<'a> Cow<'a, Path>, Path);
+impl_cmp!(<'a, 'b> Cow<'a, Path>, &'b Path);
+impl_cmp!(<'a> Cow<'a, Path>, PathBuf);
 
 #[cfg(test)]
 mod test {
@@ -354,6 +1090,7 @@ mod test {
             c.next_back();
         }
         assert_eq!(c.as_path().as_ref() as &str, remaining);
+        assert_eq!(c.as_str(), remaining);
     }
 
     #[test]
@@ -422,6 +1159,43 @@ mod test {
         assert_eq!(b.join("c").as_str(), c.as_str());
     }
 
+    #[test]
+    fn path_edit() {
+        let p = Path::new("a/b/c");
+        assert_eq!(p.edit().replace(0, "x").build(), "x/b/c");
+        assert_eq!(p.edit().replace(2, "x").build(), "a/b/x");
+        assert_eq!(p.edit().replace(1, "x/y").build(), "a/x/y/c");
+        assert_eq!(p.edit().remove(1).build(), "a/c");
+        assert_eq!(p.edit().remove(0).build(), "b/c");
+        assert_eq!(p.edit().remove(2).build(), "a/b");
+        assert_eq!(p.edit().insert(0, "x").build(), "x/a/b/c");
+        assert_eq!(p.edit().insert(3, "x").build(), "a/b/c/x");
+        assert_eq!(p.edit().insert(1, "x").remove(0).replace(2, "z").build(), "x/b/z");
+        assert!(Path::new("a").edit().remove(0).build().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn path_edit_out_of_range() {
+        Path::new("a/b").edit().insert(3, "x");
+    }
+
+    #[test]
+    fn path_div() {
+        assert_eq!(Path::new("a") / "b", PathBuf::from("a/b"));
+        assert_eq!(Path::new("a") / "b" / "c/d", PathBuf::from("a/b/c/d"));
+        let base = PathBuf::from("x/");
+        assert_eq!(&base / Path::new("y") / String::from("z"), "x/y/z");
+        assert_eq!(base / "", "x");
+    }
+
+    #[test]
+    fn path_join_all() {
+        assert_eq!(Path::new("a").join_all(["b", "c", "d"]), "a/b/c/d");
+        assert_eq!(Path::new("a").join_all(vec!["b/c", "/d/"]), "a/b/c/d");
+        assert_eq!(Path::new("a").join_all(Vec::<&str>::new()), "a");
+    }
+
     #[test]
     fn path_extension() {
         assert_eq!(Path::new("/a/b/c.txt").extension(), Some("txt"));
@@ -430,4 +1204,550 @@ mod test {
         assert_eq!(Path::new("/a/b.txt/c").extension(), None);
         assert_eq!(Path::new("/").extension(), None);
     }
+
+    #[test]
+    fn file_prefix() {
+        assert_eq!(Path::new("a/archive.tar.gz").file_prefix(), Some("archive"));
+        assert_eq!(Path::new("a.b.c").file_prefix(), Some("a"));
+        assert_eq!(Path::new("a/b/").file_prefix(), Some("b"));
+        assert_eq!(Path::new(".env").file_prefix(), Some(".env"));
+        assert_eq!(Path::new(".env.local").file_prefix(), Some(".env"));
+        assert_eq!(Path::new("a.").file_prefix(), Some("a"));
+        assert_eq!(Path::new("é.txt").file_prefix(), Some("é"));
+        assert_eq!(Path::new("/").file_prefix(), None);
+    }
+
+    #[test]
+    fn has_extension() {
+        assert!(Path::new("a/b.png").has_extension("png"));
+        assert!(!Path::new("a.PNG").has_extension("png"));
+        assert!(Path::new("a.PNG").has_extension_ignore_case("png"));
+        assert!(!Path::new("a.png.bak").has_extension("png"));
+        assert!(!Path::new("png").has_extension_ignore_case("png"));
+        assert!(!Path::new("a.d/b").has_extension("d"));
+    }
+
+    #[test]
+    fn path_eq() {
+        assert_eq!(Path::new("/a/b"), Path::new("a/b"));
+        assert_eq!(Path::new("a//b/"), PathBuf::from("/a/b"));
+        assert!(Path::new("a/b") != Path::new("a/c"));
+        assert!(Path::new("a/b") != Path::new("a/b/c"));
+    }
+
+    #[test]
+    fn path_eq_str() {
+        assert_eq!(Path::new("/a/b"), "a/b");
+        assert_eq!(Path::new("a/b/"), "a/b");
+        assert_eq!(PathBuf::from("/a/b/"), "a/b");
+        assert_eq!(PathBuf::from("a/b"), "/a/b".to_string());
+        assert_eq!("/", Path::new(""));
+        assert!(Path::new("a/b") != "a/bc");
+    }
+
+    #[test]
+    fn component_count() {
+        assert_eq!(Path::new("a//b/c").component_count(), 3);
+        assert_eq!(Path::new("/a/b/").component_count(), 2);
+        assert_eq!(Path::new("/").component_count(), 0);
+        let mut c = Path::new("a//b/c").components();
+        c.next();
+        assert_eq!(c.len(), 2);
+        assert!(!c.is_empty());
+        assert_eq!(c.next().map(|p| p.as_str()), Some("b"));
+        c.next();
+        assert!(c.is_empty());
+        assert!(Path::new("/").components().is_empty());
+    }
+
+    #[test]
+    fn components_indices() {
+        let p = Path::new("//ab/c//def/");
+        let spans: Vec<(usize, usize)> = p.components().indices().map(|(i, j, _)| (i, j)).collect();
+        assert_eq!(spans, vec![(2, 4), (5, 6), (8, 11)]);
+        for (i, j, part) in p.components().indices() {
+            assert_eq!(&p.as_str()[i..j], part.as_str());
+        }
+        let mut c = p.components();
+        c.next();
+        let mut ix = c.indices();
+        assert_eq!(ix.next_back().map(|(i, j, _)| (i, j)), Some((8, 11)));
+        assert_eq!(ix.next().map(|(i, _, p)| (i, p.as_str())), Some((5, "c")));
+        assert!(ix.next().is_none());
+        assert_eq!(Path::new("/").components().indices().count(), 0);
+    }
+
+    #[test]
+    fn components_nth() {
+        assert_eq!(Path::new("/a/b/c").components().nth(1).map(|p| p.as_str()), Some("b"));
+        assert_eq!(Path::new("a//b//c").components().nth(2).map(|p| p.as_str()), Some("c"));
+        assert!(Path::new("/a/b/c").components().nth(3).is_none());
+        let mut c = Path::new("/a/b/c/d").components();
+        assert_eq!(c.nth(1).map(|p| p.as_str()), Some("b"));
+        assert_eq!(c.next_back().map(|p| p.as_str()), Some("d"));
+        assert_eq!(c.as_path().as_str(), "c");
+    }
+
+    #[test]
+    fn components_alternating() {
+        let mut c = Path::new("a//b//c").components();
+        assert_eq!(c.next().map(Path::as_str), Some("a"));
+        assert_eq!(c.next_back().map(Path::as_str), Some("c"));
+        assert_eq!(c.as_str(), "b");
+        assert_eq!(c.next().map(Path::as_str), Some("b"));
+        assert_eq!(c.next_back(), None);
+        assert_eq!(c.next(), None);
+
+        // every mix of front and back calls sees each component once
+        for path in &["a//b//c", "//a/b//c//", "a", "/", "", "a/b/c/d/e"] {
+            let all: Vec<&str> = Path::new(path).iter().collect();
+            for mask in 0..64u32 {
+                let mut c = Path::new(path).components();
+                let (mut front, mut back) = (Vec::new(), Vec::new());
+                for k in 0..6 {
+                    if mask & (1 << k) == 0 {
+                        front.extend(c.next().map(Path::as_str));
+                    } else {
+                        back.extend(c.next_back().map(Path::as_str));
+                    }
+                }
+                front.extend(c.map(Path::as_str));
+                front.extend(back.into_iter().rev());
+                assert_eq!(front, all, "{:?} with mask {:b}", path, mask);
+            }
+        }
+    }
+
+    #[test]
+    fn iter() {
+        assert_eq!(Path::new("/a/b/c").iter().collect::<Vec<_>>(), vec!["a", "b", "c"]);
+        assert_eq!(Path::new("a//b/").iter().rev().collect::<Vec<_>>(), vec!["b", "a"]);
+        assert_eq!(Path::new("/").iter().next(), None);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("a/b".parse::<PathBuf>().unwrap(), PathBuf::from("a/b"));
+        assert_eq!("/a/b/".parse::<PathBuf>().unwrap(), "a/b");
+    }
+
+    #[test]
+    fn string_roundtrip() {
+        let long = "a/very/long/path/that/will/not/fit/inline/anywhere".to_string();
+        let s: String = PathBuf::from(long.clone()).into();
+        assert_eq!(s, long);
+        let s: String = PathBuf::from("a/b".to_string()).into();
+        assert_eq!(s, "a/b");
+    }
+
+    #[cfg(feature = "smartstring")]
+    #[test]
+    fn short_paths_inline() {
+        let mut p = PathBuf::from("a");
+        p.push("b");
+        assert!(p.inner.is_inline());
+        assert!(!PathBuf::from("a/very/long/path/that/will/not/fit/inline").inner.is_inline());
+    }
+
+    #[test]
+    fn clear_capacity() {
+        let mut p = PathBuf::from("a/very/long/path/that/will/not/fit/inline");
+        p.reserve(100);
+        let cap = p.capacity();
+        assert!(cap >= p.as_str().len());
+        p.clear();
+        assert!(p.is_empty());
+        assert_eq!(p.as_str(), "");
+        assert!(cap >= 100);
+        assert_eq!(p.capacity(), cap);
+        p.push("a");
+        assert_eq!(p, "a");
+
+        // short paths that fit inline can still reserve room
+        let mut p = PathBuf::from("a/b");
+        p.reserve(200);
+        assert_eq!(p, "a/b");
+        let cap = p.capacity();
+        assert!(cap >= 203);
+        p.push("c");
+        p.clear();
+        assert_eq!(p.capacity(), cap);
+    }
+
+    #[test]
+    fn path_hash() {
+        use std::collections::HashSet;
+
+        let mut set = HashSet::new();
+        set.insert(PathBuf::from("/a/b"));
+        assert!(set.contains(&PathBuf::from("a//b/")));
+        assert!(!set.contains(&PathBuf::from("a/b/c")));
+        assert!(!set.contains(&PathBuf::from("ab")));
+    }
+
+    #[test]
+    fn path_hash_fast_path() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash(p: &str) -> u64 {
+            let mut h = DefaultHasher::new();
+            Path::new(p).hash(&mut h);
+            h.finish()
+        }
+        assert_eq!(hash("a/b"), hash("a/b/"));
+        assert_eq!(hash("a/b"), hash("//a//b"));
+        assert_eq!(hash(""), hash("/"));
+        assert!(hash("a/b") != hash("ab"));
+        assert!(hash("a/b") != hash("a/b/c"));
+        assert_eq!(Path::new("a/b"), Path::new("a/b"));
+        assert_eq!(Path::new("a/b"), Path::new("a/b/"));
+        assert!(Path::new("a/b") != Path::new("a/c"));
+    }
+
+    #[test]
+    fn path_map_borrow() {
+        use std::collections::HashMap;
+
+        let mut map = HashMap::new();
+        map.insert(PathBuf::from("/a/b"), 1);
+        map.insert(PathBuf::from("a/b/c"), 2);
+        assert_eq!(map.get(Path::new("a/b/")), Some(&1));
+        assert_eq!(map.get(Path::new("a//b/c")), Some(&2));
+        assert_eq!(map.get(Path::new("a")), None);
+    }
+
+    #[test]
+    fn path_btreemap_range() {
+        use std::collections::BTreeMap;
+        use std::ops::Bound;
+
+        let mut map = BTreeMap::new();
+        for (i, p) in ["b", "a/z", "ab", "/a//b/", "a-b", "a", "a/b/c", "a/c", "a/b\0"].iter().enumerate() {
+            map.insert(PathBuf::from(*p), i);
+        }
+        fn keys<'a, I: Iterator<Item=(&'a PathBuf, &'a usize)>>(r: I) -> Vec<&'a str> {
+            r.map(|(k, _)| k.as_str()).collect()
+        }
+
+        let range = (Bound::Included(Path::new("a")), Bound::Excluded(Path::new("b")));
+        assert_eq!(keys(map.range::<Path, _>(range)), vec!["a", "/a//b/", "a/b/c", "a/b\0", "a/c", "a/z", "a-b", "ab"]);
+        assert_eq!(keys(map.range(Path::new("a").descendant_range())), vec!["/a//b/", "a/b/c", "a/b\0", "a/c", "a/z"]);
+        assert_eq!(keys(map.range(Path::new("/a/b/").descendant_range())), vec!["a/b/c"]);
+        assert_eq!(map.range(Path::new("").descendant_range()).count(), map.len());
+        assert_eq!(map.range(Path::new("x").descendant_range()).count(), 0);
+    }
+
+    #[test]
+    fn path_macro() {
+        assert_eq!(path!["a", "b"], PathBuf::from("a/b"));
+        assert_eq!(path!["/a/", Path::new("b"), String::from("c"),], "a/b/c");
+        assert_eq!(path![].component_count(), 0);
+    }
+
+    #[test]
+    fn path_empty() {
+        assert_eq!(PathBuf::default().component_count(), 0);
+        assert!(PathBuf::default().is_empty());
+        assert!(Path::new("").is_empty());
+        assert!(Path::new("/").is_empty());
+        assert!(Path::new("//").is_root());
+        assert!(!Path::new("/a").is_empty());
+        assert!(!Path::new("a").is_root());
+    }
+
+    #[test]
+    fn common_prefix() {
+        assert_eq!(Path::new("/a/b/c").common_prefix("/a/b/d"), "a/b");
+        assert_eq!(Path::new("a/b").common_prefix("/a/b/"), "a/b");
+        assert_eq!(Path::new("a/b").common_prefix("a/b/c/d"), "a/b");
+        assert_eq!(Path::new("a/b/c").common_prefix("a"), "a");
+        assert!(Path::new("a/b").common_prefix("x/a/b").is_empty());
+        assert!(Path::new("ab").common_prefix("a").is_empty());
+    }
+
+    #[test]
+    fn contains() {
+        let p = Path::new("/a/b/c/d");
+        assert!(p.contains("b/c"));
+        assert!(p.contains("/c/d/"));
+        assert!(p.contains("a/b/c/d"));
+        assert!(p.contains(""));
+        assert!(!p.contains("a/c"));
+        assert!(!p.contains("d/e"));
+        assert!(!p.contains("b/c/d/e/f"));
+        assert!(!Path::new("ab/c").contains("b/c"));
+    }
+
+    #[test]
+    fn relative_to() {
+        assert_eq!(Path::new("a/b/c").relative_to("a/x"), "../b/c");
+        assert_eq!(Path::new("/a/b/c").relative_to("a"), "b/c");
+        assert_eq!(Path::new("a").relative_to("a/b/c"), "../..");
+        assert_eq!(Path::new("x/y").relative_to("a/b"), "../../x/y");
+        assert!(Path::new("/a/b/").relative_to("a/b").is_empty());
+    }
+
+    #[test]
+    fn strip_prefix() {
+        assert_eq!(Path::new("/a/b/c").strip_prefix("a").unwrap(), "b/c");
+        assert_eq!(Path::new("/a/b/c").strip_prefix("a/b").unwrap().as_str(), "c");
+        assert!(Path::new("a/b").strip_prefix("a/b/").unwrap().is_empty());
+        assert_eq!(Path::new("a/b").strip_prefix("").unwrap(), "a/b");
+        assert!(Path::new("a/bc").strip_prefix("a/b").is_none());
+        assert!(Path::new("a").strip_prefix("a/b").is_none());
+    }
+
+    #[test]
+    fn strip_suffix() {
+        assert_eq!(Path::new("a/b/c/d").strip_suffix("c/d").unwrap().as_str(), "a/b");
+        assert_eq!(Path::new("/a/b/c/").strip_suffix("/c").unwrap().as_str(), "/a/b");
+        assert!(Path::new("a/b").strip_suffix("/a/b/").unwrap().is_empty());
+        assert_eq!(Path::new("a/b").strip_suffix("").unwrap(), "a/b");
+        assert!(Path::new("a/bc").strip_suffix("c").is_none());
+        assert!(Path::new("b").strip_suffix("a/b").is_none());
+    }
+
+    #[test]
+    fn starts_ends_with() {
+        assert!(Path::new("/a/b/c").starts_with("a//b"));
+        assert!(Path::new("a/b").starts_with(""));
+        assert!(!Path::new("a/bc").starts_with("a/b"));
+        assert!(Path::new("a/b/c.png").ends_with("b/c.png/"));
+        assert!(!Path::new("a/b/c.png").ends_with("png"));
+        assert!(!Path::new("c").ends_with("b/c"));
+    }
+
+    #[test]
+    fn display_relative() {
+        assert_eq!(Path::new("/a/b/c").display_relative("/a").to_string(), "b/c");
+        assert_eq!(Path::new("/a/b/c").display_relative("/x").to_string(), "a/b/c");
+        assert_eq!(Path::new("a//b/").display_relative("").to_string(), "a/b");
+    }
+
+    #[test]
+    fn components_peek() {
+        let mut c = Path::new("/a/b/c").components();
+        assert_eq!(c.peek().map(|p| p.as_str()), Some("a"));
+        assert_eq!(c.peek_back().map(|p| p.as_str()), Some("c"));
+        assert_eq!(c.as_path().as_str(), "/a/b/c");
+        assert_eq!(c.next().map(|p| p.as_str()), Some("a"));
+        assert_eq!(c.peek().map(|p| p.as_str()), Some("b"));
+        assert_eq!(c.next_back().map(|p| p.as_str()), Some("c"));
+        assert_eq!(c.peek_back().map(|p| p.as_str()), Some("b"));
+        c.next();
+        assert!(c.peek().is_none());
+        assert!(c.peek_back().is_none());
+    }
+
+    #[test]
+    fn eq_ignore_case() {
+        assert!(Path::new("/A/B").eq_ignore_case("a/b"));
+        assert!(Path::new("/A/B") != Path::new("a/b"));
+        assert!(!Path::new("A/B").eq_ignore_case("a/b/c"));
+        assert!(!Path::new("A/B").eq_ignore_case("a/c"));
+        assert_eq!(Path::new("/Foo/BAR.txt").to_ascii_lowercase(), "foo/bar.txt");
+    }
+
+    #[test]
+    fn is_safe() {
+        assert!(Path::new("/a/b.c/..d").is_safe());
+        assert!(Path::new("").is_safe());
+        assert!(!Path::new("a/../b").is_safe());
+        assert!(!Path::new("./a").is_safe());
+        assert!(!Path::new("/a/b/..").is_safe());
+    }
+
+    #[test]
+    fn map_components() {
+        let upper = Path::new("a/b/c").map_components(|c| PathBuf::from(c.as_str().to_uppercase()));
+        assert_eq!(upper, "A/B/C");
+        let nested = Path::new("/a/b").map_components(|c| c.join("x"));
+        assert_eq!(nested, "a/x/b/x");
+    }
+
+    #[test]
+    fn split_first_last() {
+        let (first, rest) = Path::new("/a/b/c").split_first().unwrap();
+        assert_eq!((first, rest), ("a", Path::new("b/c")));
+        let (last, parent) = Path::new("/a/b/c").split_last().unwrap();
+        assert_eq!((last, parent), ("c", Path::new("a/b")));
+        let (first, rest) = Path::new("a/").split_first().unwrap();
+        assert_eq!(first, "a");
+        assert!(rest.is_empty());
+        let (last, parent) = Path::new("/a").split_last().unwrap();
+        assert_eq!(last, "a");
+        assert!(parent.is_empty());
+        assert!(Path::new("/").split_first().is_none());
+        assert!(Path::new("").split_last().is_none());
+    }
+
+    #[test]
+    fn is_hidden() {
+        assert!(Path::new(".hidden").is_hidden());
+        assert!(Path::new("a/.git/").is_hidden());
+        assert!(!Path::new("visible.txt").is_hidden());
+        assert!(!Path::new("a/.b/c").is_hidden());
+        assert!(!Path::new("").is_hidden());
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(Path::new("a/b") < Path::new("a-b"));
+        assert!(Path::new("/a") < Path::new("a/b"));
+        assert_eq!(Path::new("a//b/").cmp(Path::new("/a/b")), cmp::Ordering::Equal);
+        let mut v = vec![PathBuf::from("b"), PathBuf::from("a/c"), PathBuf::from("a")];
+        v.sort();
+        assert_eq!(v, vec![PathBuf::from("a"), PathBuf::from("a/c"), PathBuf::from("b")]);
+    }
+
+    #[test]
+    fn ordering_str() {
+        assert!(Path::new("a/b") < "a/c");
+        assert!(Path::new("a/b") == "a/b/");
+        assert!("a/b/c" > Path::new("a/b"));
+        assert!(PathBuf::from("a/b") < "a-b".to_string());
+        assert_eq!(PathBuf::from("/a/b").partial_cmp("a//b"), Some(cmp::Ordering::Equal));
+    }
+
+    #[test]
+    fn normalized_str() {
+        assert_eq!(Path::new("//a//b//").normalized_str(), "a/b");
+        assert_eq!(Path::new("/").normalized_str(), "");
+        assert_eq!(Path::new("").normalized_str(), "");
+        match Path::new("a/b").normalized_str() {
+            Cow::Borrowed(s) => assert_eq!(s, "a/b"),
+            Cow::Owned(_) => panic!("normal paths shouldn't allocate"),
+        }
+    }
+
+    #[test]
+    fn push_edges() {
+        for base in &["a", "a/"] {
+            let mut p = PathBuf::from(*base);
+            p.push("");
+            assert_eq!(p.as_str(), *base);
+            p.push("/");
+            assert_eq!(p.as_str(), *base);
+            p.push("/b");
+            assert_eq!(p.as_str(), "a/b");
+            p.push("c//d/");
+            assert_eq!(p.as_str(), "a/b/c/d");
+        }
+        let mut p = PathBuf::new();
+        p.push("/a/");
+        assert_eq!(p.as_str(), "a");
+        assert_eq!(path!["a", "", "/", "b"].as_str(), "a/b");
+    }
+
+    #[test]
+    fn from_normalized() {
+        assert_eq!(PathBuf::from_normalized("a//b/../c"), "a/c");
+        assert_eq!(PathBuf::from_normalized("a//b/../c").as_str(), "a/c");
+        assert_eq!(PathBuf::from_normalized("/./a/b/./").as_str(), "a/b");
+        assert_eq!(PathBuf::from_normalized("../../a/..//b").as_str(), "b");
+        assert!(PathBuf::from_normalized("a/..").is_empty());
+        assert!(PathBuf::from_normalized("../x/../..").is_safe());
+        assert_eq!(PathBuf::from_normalized(String::from("a/b")).as_str(), "a/b");
+    }
+
+    #[test]
+    fn prepend() {
+        let mut p = PathBuf::from("b/c");
+        p.prepend("a");
+        assert_eq!(p.as_str(), "a/b/c");
+        p.prepend("/x//y/");
+        assert_eq!(p.as_str(), "x/y/a/b/c");
+        p.prepend("");
+        p.prepend("/");
+        assert_eq!(p.as_str(), "x/y/a/b/c");
+
+        let mut p = PathBuf::from("/b");
+        p.prepend("a");
+        assert_eq!(p.as_str(), "a/b");
+
+        let mut p = PathBuf::new();
+        p.prepend("/a/");
+        assert_eq!(p.as_str(), "a");
+        let mut p = PathBuf::from("/");
+        p.prepend("a");
+        assert_eq!(p.as_str(), "a");
+    }
+
+    #[test]
+    fn os_path() {
+        use std::ffi::OsStr;
+        use std::path as os;
+
+        let p = Path::new("/a//b/").to_os_path();
+        assert!(p.is_relative());
+        assert_eq!(p.iter().collect::<Vec<_>>(), vec![OsStr::new("a"), OsStr::new("b")]);
+        assert!(Path::new("/").to_os_path().as_os_str().is_empty());
+        assert_eq!(PathBuf::from_os_path(&p).unwrap(), "a/b");
+        assert_eq!(PathBuf::from_os_path(os::Path::new("/x/../y")).unwrap().as_str(), "x/../y");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn os_path_windows() {
+        use std::path as os;
+
+        assert_eq!(Path::new("a/b").to_os_path(), os::PathBuf::from("a\\b"));
+        assert_eq!(PathBuf::from_os_path(os::Path::new("a\\b")).unwrap(), "a/b");
+        assert!(PathBuf::from_os_path(os::Path::new("C:\\a")).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn os_path_not_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+        use std::path as os;
+
+        assert!(PathBuf::from_os_path(os::Path::new(OsStr::from_bytes(b"a/\xff"))).is_none());
+    }
+
+    #[test]
+    fn cow_eq() {
+        let owned: Cow<Path> = Cow::Owned(PathBuf::from("a/b"));
+        assert_eq!(owned, Path::new("/a/b/"));
+        assert_eq!(Path::new("a//b"), owned);
+        assert_eq!(owned, *Path::new("a/b"));
+        assert_eq!(owned, PathBuf::from("a/b"));
+        let borrowed: Cow<Path> = Cow::Borrowed(Path::new("a/c"));
+        assert!(borrowed != Path::new("a/b"));
+        assert!(owned < borrowed);
+    }
+
+    #[test]
+    fn into_iter_owned() {
+        let mut parts = Vec::new();
+        for seg in PathBuf::from("/a//b/c/") {
+            parts.push(seg);
+        }
+        assert_eq!(parts, vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+        assert_eq!(PathBuf::new().into_iter().count(), 0);
+    }
+
+    #[test]
+    fn slice() {
+        use std::ops::Bound;
+
+        let p = Path::new("/a/b//c/d/");
+        assert_eq!(p.slice(1..3).as_str(), "b//c");
+        assert_eq!(p.slice(1..=1).as_str(), "b");
+        assert_eq!(p.slice(..).as_str(), "a/b//c/d");
+        assert_eq!(p.slice(2..).as_str(), "c/d");
+        assert_eq!(p.slice(..1).as_str(), "a");
+        assert!(p.slice(2..2).is_empty());
+        assert!(p.slice((Bound::Included(3), Bound::Excluded(1))).is_empty());
+        // out of range indices are clamped
+        assert_eq!(p.slice(2..10).as_str(), "c/d");
+        assert!(p.slice(7..).is_empty());
+        assert!(p.slice(usize::MAX..).is_empty());
+        assert!(p.slice((Bound::Excluded(1 << 34), Bound::Unbounded)).is_empty());
+        assert!(p.slice(7..9).is_empty());
+        assert!(Path::new("").slice(..).is_empty());
+        // borrowed from the original
+        assert_eq!(p.slice(1..3).as_str().as_ptr(), p.as_str()[3..].as_ptr());
+    }
 }
@@ -0,0 +1,220 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Result, FileType, Metadata, OpenOptions, Permissions, QPath, Requalify};
+
+// errors that may well go away if the operation is tried again
+fn retryable(kind: io::ErrorKind) -> bool {
+    match kind {
+        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => true,
+        _ => false,
+    }
+}
+
+/// A filesystem wrapper that retries operations that fail transiently.
+///
+/// `open`, `file_type`, `read_dir`, `create`, `append` and
+/// `open_options` are tried again when they fail with `TimedOut`, `Interrupted` or
+/// `WouldBlock`, sleeping between attempts and doubling the sleep each
+/// time. Any other error is returned immediately, as is the last
+/// error once the retries run out.
+pub struct Retry<T> {
+    inner: T,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl<T> Retry<T> {
+    /// Wrap a filesystem, retrying up to `retries` times and first
+    /// waiting `backoff` before a retry.
+    pub fn new(inner: T, retries: u32, backoff: Duration) -> Retry<T> {
+        Retry { inner: inner, retries: retries, backoff: backoff }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    fn retry<R, F: FnMut() -> Result<R>>(&self, mut f: F) -> Result<R> {
+        let mut wait = self.backoff;
+        let mut left = self.retries;
+        loop {
+            match f() {
+                Err(ref e) if left > 0 && retryable(e.kind()) => {
+                    thread::sleep(wait);
+                    wait *= 2;
+                    left -= 1;
+                }
+                r => return r,
+            }
+        }
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Retry<T> {
+    type ReadFile = T::ReadFile;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<T::ReadFile> {
+        self.retry(|| self.inner.open(&path))
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(T::ReadFile, Metadata)> {
+        self.retry(|| self.inner.open_meta(&path))
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.retry(|| self.inner.file_type(&path))
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, Retry<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.retry(|| self.inner.read_dir(&path)).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.retry(|| self.inner.read_dir_all(&path)).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let prefix = prefix.as_ref().to_path_buf();
+        let files = self.retry(|| self.inner.read_dir_recursive_flat(prefix.clone()))?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+impl<'a, T: FSWrite<'a>> FSWrite<'a> for Retry<T> {
+    type WriteFile = T::WriteFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.retry(|| self.inner.create(&path))
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.retry(|| self.inner.append(&path))
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<T::WriteFile> {
+        self.retry(|| self.inner.open_options(&path, opts))
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        self.inner.truncate(path, len)
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.inner.set_permissions(path, perm)
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.inner.hard_link(existing, link)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.remove_dir(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.inner.create_dir(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{Error, FSRead, FSWrite, MemFS, Counting};
+    use std::io::{Read, Write};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // fails each call with `kind` until `failures` calls have failed
+    struct Flaky {
+        inner: MemFS,
+        kind: io::ErrorKind,
+        failures: AtomicUsize,
+    }
+
+    impl Flaky {
+        fn check<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+            if self.failures.load(Ordering::SeqCst) > 0 {
+                self.failures.fetch_sub(1, Ordering::SeqCst);
+                return Err(Error::new("open", path, io::Error::new(self.kind, "Flaky.")));
+            }
+            Ok(())
+        }
+    }
+
+    impl<'a> FSRead<'a> for Flaky {
+        type ReadFile = io::Cursor<Vec<u8>>;
+
+        fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Cursor<Vec<u8>>> {
+            self.check(&path)?;
+            self.inner.open(path)
+        }
+
+        fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+            self.inner.file_type(path)
+        }
+
+        type ReadDir = Requalify<'a, <MemFS as FSRead<'a>>::ReadDir, Flaky>;
+
+        fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+            self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+        }
+    }
+
+    fn flaky(kind: io::ErrorKind, failures: usize) -> Retry<Counting<Flaky>> {
+        let m = MemFS::new();
+        m.create("a").unwrap().write_all(b"data").unwrap();
+        let f = Flaky { inner: m, kind: kind, failures: AtomicUsize::new(failures) };
+        Retry::new(Counting::new(f), 3, Duration::from_millis(1))
+    }
+
+    #[test]
+    fn retry_transient() {
+        let r = flaky(io::ErrorKind::TimedOut, 2);
+        let mut s = String::new();
+        r.open("a").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "data");
+        assert_eq!(r.get_ref().opens(), 3);
+
+        let r = flaky(io::ErrorKind::WouldBlock, 10);
+        assert_eq!(r.open("a").unwrap_err().kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(r.get_ref().opens(), 4);
+    }
+
+    #[test]
+    fn retry_permanent() {
+        let r = flaky(io::ErrorKind::PermissionDenied, 1);
+        assert_eq!(r.open("a").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(r.get_ref().opens(), 1);
+        assert_eq!(r.open("missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(r.get_ref().opens(), 2);
+    }
+}
@@ -0,0 +1,390 @@
+use std::{io, mem, vec};
+use std::sync::Arc;
+use aws_sdk_s3::Client;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::primitives::ByteStream;
+use tokio::runtime::{Builder, Runtime};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, QPath};
+
+/// A filesystem in an S3 bucket.
+///
+/// Each file is an object whose key is its path, with components
+/// joined by "/". S3 has no directories, so a directory exists
+/// wherever some key lies underneath it; `create_dir` stores an empty
+/// "dir/" marker object so new directories show up before anything
+/// is written into them. Files can be created anywhere, without
+/// creating their parents first.
+///
+/// Objects are read into memory in full on `open`. Writes are
+/// buffered and uploaded when the file is flushed or dropped, so flush
+/// explicitly to see any upload errors. Requests are run on an
+/// internal runtime, so this must not be used from inside another
+/// async runtime.
+pub struct S3FS {
+    bucket: Arc<Bucket>,
+}
+
+// everything needed to make requests, shared with open files
+struct Bucket {
+    client: Client,
+    name: String,
+    rt: Runtime,
+}
+
+// paths are stored as their components joined by "/", so "" is the root
+fn key<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref().iter().collect::<Vec<_>>().join("/")
+}
+
+// the prefix shared by every key inside a directory, and its marker
+fn dir_prefix<P: AsRef<Path>>(path: P) -> String {
+    let k = key(path);
+    if k.is_empty() { k } else { k + "/" }
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+// map an SDK error onto an io::ErrorKind by its HTTP status
+fn sdk_error<P: AsRef<Path>, E>(op: &'static str, path: P, err: SdkError<E, HttpResponse>) -> Error
+    where E: ::std::error::Error + Send + Sync + 'static
+{
+    let kind = match err.raw_response().map(|r| r.status().as_u16()) {
+        Some(404) => io::ErrorKind::NotFound,
+        Some(401) | Some(403) => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    Error::new(op, path, io::Error::new(kind, err))
+}
+
+// percent-encode a key for use in a copy source
+fn encode(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    for b in key.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+impl Bucket {
+    fn put<P: AsRef<Path>>(&self, op: &'static str, path: P, key: String, data: Vec<u8>) -> Result<()> {
+        let req = self.client.put_object().bucket(&self.name).key(key).body(ByteStream::from(data));
+        self.rt.block_on(req.send()).map(|_| ()).map_err(|e| sdk_error(op, path, e))
+    }
+
+    fn delete<P: AsRef<Path>>(&self, op: &'static str, path: P, key: String) -> Result<()> {
+        let req = self.client.delete_object().bucket(&self.name).key(key);
+        self.rt.block_on(req.send()).map(|_| ()).map_err(|e| sdk_error(op, path, e))
+    }
+}
+
+impl S3FS {
+    /// Use the objects in the named bucket, through a configured client.
+    pub fn new(client: Client, bucket: &str) -> Result<S3FS> {
+        let rt = Builder::new_current_thread().enable_all().build()?;
+        Ok(S3FS { bucket: Arc::new(Bucket { client: client, name: bucket.to_string(), rt: rt }) })
+    }
+
+    fn get<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<Vec<u8>> {
+        let b = &self.bucket;
+        let req = b.client.get_object().bucket(&b.name).key(key(&path));
+        let resp = b.rt.block_on(req.send()).map_err(|e| sdk_error(op, &path, e))?;
+        let body = b.rt.block_on(resp.body.collect()).map_err(|e| Error::new(op, &path, io::Error::new(io::ErrorKind::Other, e)))?;
+        Ok(body.into_bytes().to_vec())
+    }
+
+    fn is_object<P: AsRef<Path>>(&self, path: P) -> bool {
+        let b = &self.bucket;
+        let req = b.client.head_object().bucket(&b.name).key(key(&path));
+        !path.as_ref().is_empty() && b.rt.block_on(req.send()).is_ok()
+    }
+
+    // keys under a directory, either direct children only or everything,
+    // optionally stopping after the first page
+    fn list<P: AsRef<Path>>(&self, op: &'static str, path: P, children: bool, limit: Option<i32>) -> Result<Vec<String>> {
+        let b = &self.bucket;
+        let prefix = dir_prefix(&path);
+        let mut keys = Vec::new();
+        let mut token = None;
+        loop {
+            let mut req = b.client.list_objects_v2().bucket(&b.name).prefix(prefix.clone())
+                .set_continuation_token(token.take())
+                .set_max_keys(limit);
+            if children {
+                req = req.delimiter("/");
+            }
+            let resp = b.rt.block_on(req.send()).map_err(|e| sdk_error(op, &path, e))?;
+            keys.extend(resp.contents().iter().filter_map(|o| o.key()).map(String::from));
+            keys.extend(resp.common_prefixes().iter().filter_map(|p| p.prefix()).map(String::from));
+            match resp.next_continuation_token() {
+                Some(t) if limit.is_none() && resp.is_truncated() == Some(true) => token = Some(t.to_string()),
+                _ => break,
+            }
+        }
+        // the directory's own marker isn't an entry in it
+        keys.retain(|k| k != &prefix);
+        Ok(keys)
+    }
+
+    fn writer<P: AsRef<Path>>(&self, path: P, data: Vec<u8>) -> S3File {
+        S3File { bucket: self.bucket.clone(), path: path.as_ref().to_path_buf(), data: data, dirty: false }
+    }
+
+    fn move_object<P: AsRef<Path>>(&self, path: P, from: &str, to: &str) -> Result<()> {
+        let b = &self.bucket;
+        let req = b.client.copy_object().bucket(&b.name)
+            .copy_source(format!("{}/{}", b.name, encode(from)))
+            .key(to);
+        b.rt.block_on(req.send()).map_err(|e| sdk_error("rename", &path, e))?;
+        b.delete("rename", path, from.to_string())
+    }
+}
+
+/// A writable file on an `S3FS`.
+///
+/// The contents are uploaded in full on every flush, and on drop if
+/// anything was written since the last flush.
+pub struct S3File {
+    bucket: Arc<Bucket>,
+    path: PathBuf,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+impl S3File {
+    /// Upload any pending writes, and return the final contents.
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        io::Write::flush(&mut self)?;
+        Ok(mem::replace(&mut self.data, Vec::new()))
+    }
+}
+
+impl io::Write for S3File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        self.dirty = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.dirty {
+            self.bucket.put("create", &self.path, key(&self.path), self.data.clone())?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for S3File {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+pub struct ReadDir<'a> {
+    iter: vec::IntoIter<PathBuf>,
+    parent: &'a S3FS,
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = QPath<'a, S3FS>;
+
+    fn next(&mut self) -> Option<QPath<'a, S3FS>> {
+        self.iter.next().map(|p| self.parent.qualified(p))
+    }
+}
+
+impl<'a> FSRead<'a> for S3FS {
+    type ReadFile = io::Cursor<Vec<u8>>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Cursor<Vec<u8>>> {
+        self.get("open", path).map(io::Cursor::new)
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        if path.as_ref().is_empty() {
+            return Ok(FileType::Dir);
+        }
+        if self.is_object(&path) {
+            return Ok(FileType::File);
+        }
+        // a directory is anything with keys underneath it, or a marker
+        let b = &self.bucket;
+        let req = b.client.list_objects_v2().bucket(&b.name).prefix(dir_prefix(&path)).max_keys(1);
+        let resp = b.rt.block_on(req.send()).map_err(|e| sdk_error("stat", &path, e))?;
+        if resp.contents().is_empty() {
+            Err(error("stat", path, io::ErrorKind::NotFound, "File not found."))
+        } else {
+            Ok(FileType::Dir)
+        }
+    }
+
+    type ReadDir = ReadDir<'a>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a>> {
+        if !self.file_type(&path)?.is_dir() {
+            return Err(error("read directory", path, io::ErrorKind::Other, "Not a directory."));
+        }
+        // subdirectories come back as prefixes, with a trailing "/"
+        let mut children: Vec<String> = self.list("read directory", &path, true, None)?;
+        children.sort();
+        children.dedup();
+        let children: Vec<PathBuf> = children.iter().map(|k| PathBuf::from(k.trim_end_matches('/'))).collect();
+        Ok(ReadDir { iter: children.into_iter(), parent: self })
+    }
+}
+
+impl<'a> FSWrite<'a> for S3FS {
+    type WriteFile = S3File;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<S3File> {
+        if path.as_ref().is_empty() {
+            return Err(error("create", path, io::ErrorKind::Other, "Is a directory."));
+        }
+        self.bucket.put("create", &path, key(&path), Vec::new())?;
+        Ok(self.writer(path, Vec::new()))
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<S3File> {
+        let data = self.get("append to", &path)?;
+        Ok(self.writer(path, data))
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        let mut data = self.get("truncate", &path)?;
+        data.resize(len as usize, 0);
+        self.bucket.put("truncate", &path, key(&path), data)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        // objects move one at a time, so moving a directory isn't atomic
+        let moves = match self.file_type(&from) {
+            Ok(FileType::File) => vec![(key(&from), key(&to))],
+            Ok(FileType::Dir) if !from.as_ref().is_empty() => {
+                let (old, new) = (dir_prefix(&from), dir_prefix(&to));
+                let mut keys = self.list("rename", &from, false, None)?;
+                keys.push(old.clone());
+                keys.into_iter().map(|k| { let moved = format!("{}{}", new, &k[old.len()..]); (k, moved) }).collect()
+            }
+            Ok(FileType::Dir) => return Err(error("rename", from, io::ErrorKind::PermissionDenied, "Cannot move the root.")),
+            Err(e) => return Err(Error::new("rename", &from, e.into_io_error())),
+        };
+        for (old, new) in moves {
+            match self.move_object(&from, &old, &new) {
+                // directories without a marker have nothing to move
+                Err(_) if old.ends_with('/') => {}
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if !self.is_object(&path) {
+            return Err(error("remove", path, io::ErrorKind::NotFound, "File not found."));
+        }
+        self.bucket.delete("remove", &path, key(&path))
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if path.as_ref().is_empty() {
+            return Err(error("remove", path, io::ErrorKind::PermissionDenied, "Cannot remove the root."));
+        }
+        if !self.file_type(&path)?.is_dir() {
+            return Err(error("remove", path, io::ErrorKind::Other, "Not a directory."));
+        }
+        if !self.list("remove", &path, true, Some(2))?.is_empty() {
+            return Err(error("remove", path, io::ErrorKind::Other, "Directory not empty."));
+        }
+        self.bucket.delete("remove", &path, dir_prefix(&path))
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if self.exists(&path) {
+            return Err(error("create directory", path, io::ErrorKind::AlreadyExists, "File exists."));
+        }
+        self.bucket.put("create directory", &path, dir_prefix(&path), Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite};
+    use aws_sdk_s3::Config;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_s3::config::retry::RetryConfig;
+    use mockito::{Matcher, Server};
+    use std::io::{Read, Write};
+
+    const LISTING: &'static str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+        <Name>bucket</Name><Prefix>dir/</Prefix><Delimiter>/</Delimiter>\
+        <KeyCount>3</KeyCount><MaxKeys>1000</MaxKeys><IsTruncated>false</IsTruncated>\
+        <Contents><Key>dir/</Key><Size>0</Size></Contents>\
+        <Contents><Key>dir/a.txt</Key><Size>5</Size></Contents>\
+        <CommonPrefixes><Prefix>dir/sub/</Prefix></CommonPrefixes>\
+        </ListBucketResult>";
+
+    fn client(url: &str) -> Client {
+        let config = Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .endpoint_url(url)
+            .force_path_style(true)
+            .credentials_provider(Credentials::new("key", "secret", None, None, "test"))
+            .retry_config(RetryConfig::disabled())
+            .build();
+        Client::from_conf(config)
+    }
+
+    #[test]
+    fn s3() {
+        let mut server = Server::new();
+        let get = server.mock("GET", "/bucket/dir/a.txt").match_query(Matcher::Any).with_body("hello").create();
+        let missing = server.mock("GET", "/bucket/missing").match_query(Matcher::Any).with_status(404).create();
+        server.mock("HEAD", "/bucket/dir").match_query(Matcher::Any).with_status(404).create();
+        let stat = server.mock("GET", Matcher::Regex("^/bucket/?$".into()))
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("prefix".into(), "dir/".into()),
+                Matcher::UrlEncoded("max-keys".into(), "1".into()),
+            ]))
+            .with_body(LISTING)
+            .create();
+        let list = server.mock("GET", Matcher::Regex("^/bucket/?$".into()))
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("list-type".into(), "2".into()),
+                Matcher::UrlEncoded("prefix".into(), "dir/".into()),
+                Matcher::UrlEncoded("delimiter".into(), "/".into()),
+            ]))
+            .with_body(LISTING)
+            .create();
+        let put = server.mock("PUT", "/bucket/new.txt").match_query(Matcher::Any).expect(2).create();
+
+        let s3 = S3FS::new(client(&server.url()), "bucket").unwrap();
+        let mut s = String::new();
+        s3.open("dir/a.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+        assert_eq!(s3.open("missing").err().unwrap().kind(), io::ErrorKind::NotFound);
+
+        let names: Vec<PathBuf> = s3.read_dir("dir").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(names, vec!["dir/a.txt", "dir/sub"]);
+        assert_eq!(names[1].as_str(), "dir/sub");
+
+        let mut f = s3.create("new.txt").unwrap();
+        f.write_all(b"data").unwrap();
+        assert_eq!(f.finish().unwrap(), b"data");
+
+        get.assert();
+        missing.assert();
+        stat.assert();
+        list.assert();
+        put.assert();
+    }
+}
@@ -0,0 +1,138 @@
+use std::io;
+use tar::{Archive, EntryType};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, Metadata, QPath, Requalify};
+use mem::{MemFS, ReadDir as MemReadDir};
+
+/// A read-only filesystem over the members of a tar archive.
+///
+/// Tar archives can only be read in order, so the whole archive is
+/// indexed into memory when the `TarFS` is created. Directories are
+/// synthesized for every member path, whether or not the archive
+/// contains explicit directory entries, and a leading "./" on member
+/// names is ignored. To read a `.tar.gz`, wrap the reader in a gzip
+/// decoder first.
+pub struct TarFS {
+    inner: MemFS,
+}
+
+// member names, without "." components or a leading slash
+fn member_path(name: &str) -> Option<PathBuf> {
+    let mut p = PathBuf::new();
+    for part in Path::new(name) {
+        match part.as_str() {
+            "." => {}
+            ".." => return None,
+            s => p.push(s),
+        }
+    }
+    Some(p)
+}
+
+fn invalid<E: ::std::error::Error + Send + Sync + 'static>(err: E) -> Error {
+    Error::from(io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+impl TarFS {
+    /// Read and index a tar archive.
+    pub fn new<R: io::Read>(reader: R) -> Result<TarFS> {
+        let inner = MemFS::new();
+        let mut archive = Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = {
+                let raw = entry.path()?;
+                match raw.to_str().and_then(member_path) {
+                    Some(p) => p,
+                    None => continue,
+                }
+            };
+            match entry.header().entry_type() {
+                EntryType::Directory => inner.create_dir_all(&name)?,
+                EntryType::Regular | EntryType::Continuous => {
+                    if let Some(parent) = name.parent() {
+                        inner.create_dir_all(parent)?;
+                    }
+                    let mut f = inner.create(&name)?;
+                    io::copy(&mut entry, &mut f).map_err(invalid)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(TarFS { inner: inner })
+    }
+}
+
+impl<'a> FSRead<'a> for TarFS {
+    type ReadFile = io::Cursor<Vec<u8>>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<io::Cursor<Vec<u8>>> {
+        self.inner.open(path)
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.inner.file_type(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    type ReadDir = Requalify<'a, MemReadDir<'a>, TarFS>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::FSRead;
+    use std::io::Read;
+    use tar::{Builder, Header};
+
+    fn tarball() -> Vec<u8> {
+        let mut b = Builder::new(Vec::new());
+        for &(name, data) in &[("./hello.txt", "hello"), ("./sub/nested.txt", "nested"), ("top/a", "a")] {
+            let mut h = Header::new_gnu();
+            h.set_size(data.len() as u64);
+            h.set_mode(0o644);
+            h.set_entry_type(EntryType::Regular);
+            {
+                let raw = &mut h.as_old_mut().name;
+                raw[..name.len()].copy_from_slice(name.as_bytes());
+            }
+            h.set_cksum();
+            b.append(&h, data.as_bytes()).unwrap();
+        }
+        b.into_inner().unwrap()
+    }
+
+    #[test]
+    fn tar_read() {
+        let t = TarFS::new(io::Cursor::new(tarball())).unwrap();
+        let mut s = String::new();
+        t.open("sub/nested.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "nested");
+        assert!(t.is_file("hello.txt"));
+        assert!(t.is_dir("sub"));
+        assert!(t.is_dir("top"));
+
+        let root: Vec<PathBuf> = t.read_dir("").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(root, vec!["hello.txt", "sub", "top"]);
+        let sub: Vec<PathBuf> = t.read_dir("sub").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(sub, vec!["sub/nested.txt"]);
+    }
+}
@@ -0,0 +1,184 @@
+use std::io;
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Result, FileType, Metadata, OpenOptions, Permissions, QPath, Requalify};
+
+/// A filesystem wrapper that writes everything to two filesystems.
+///
+/// Every write operation is applied to the first filesystem and then
+/// to the second, and files written through `Tee` send each write to
+/// both. This fails fast: the first error from either side is
+/// returned straight away, which can leave the two out of step. Reads
+/// only go to the first filesystem.
+pub struct Tee<A, B> {
+    a: A,
+    b: B,
+}
+
+impl<A, B> Tee<A, B> {
+    pub fn new(a: A, b: B) -> Tee<A, B> {
+        Tee { a: a, b: b }
+    }
+
+    pub fn get_ref(&self) -> (&A, &B) {
+        (&self.a, &self.b)
+    }
+}
+
+/// A file opened for writing through `Tee`.
+pub struct TeeFile<W, X> {
+    a: W,
+    b: X,
+}
+
+impl<W: io::Write, X: io::Write> io::Write for TeeFile<W, X> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // partial writes would leave the two sides different
+        self.a.write_all(buf)?;
+        self.b.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.a.flush()?;
+        self.b.flush()
+    }
+}
+
+impl<'a, A: FSRead<'a>, B: 'a> FSRead<'a> for Tee<A, B> {
+    type ReadFile = A::ReadFile;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<A::ReadFile> {
+        self.a.open(path)
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(A::ReadFile, Metadata)> {
+        self.a.open_meta(path)
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.a.file_type(path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.a.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.a.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.a.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.a.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, A::ReadDir, Tee<A, B>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.a.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.a.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.a.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+impl<'a, A: FSWrite<'a>, B: FSWrite<'a>> FSWrite<'a> for Tee<A, B> {
+    type WriteFile = TeeFile<A::WriteFile, B::WriteFile>;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<Self::WriteFile> {
+        Ok(TeeFile { a: self.a.create(&path)?, b: self.b.create(path)? })
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<Self::WriteFile> {
+        Ok(TeeFile { a: self.a.append(&path)?, b: self.b.append(path)? })
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<Self::WriteFile> {
+        Ok(TeeFile { a: self.a.open_options(&path, opts)?, b: self.b.open_options(path, opts)? })
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        self.a.truncate(&path, len)?;
+        self.b.truncate(path, len)
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.a.set_permissions(&path, perm)?;
+        self.b.set_permissions(path, perm)
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.a.hard_link(&existing, &link)?;
+        self.b.hard_link(existing, link)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.a.rename(&from, &to)?;
+        self.b.rename(from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.a.remove_file(&path)?;
+        self.b.remove_file(path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.a.remove_dir(&path)?;
+        self.b.remove_dir(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.a.create_dir(&path)?;
+        self.b.create_dir(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS};
+    use std::io::{Read, Write};
+
+    fn contents<'a, T: FSRead<'a>>(fs: &T, path: &str) -> String {
+        let mut s = String::new();
+        fs.open(path).ok().unwrap().read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn tee_mirror() {
+        let t = Tee::new(MemFS::new(), MemFS::new());
+        t.create_dir("out").unwrap();
+        t.create("out/a").unwrap().write_all(b"both").unwrap();
+        t.append("out/a").unwrap().write_all(b"!").unwrap();
+        let (a, b) = t.get_ref();
+        assert_eq!(contents(a, "out/a"), "both!");
+        assert_eq!(contents(b, "out/a"), "both!");
+        assert_eq!(contents(&t, "out/a"), "both!");
+
+        t.rename("out/a", "out/b").unwrap();
+        assert!(a.is_file("out/b") && b.is_file("out/b"));
+        t.remove_file("out/b").unwrap();
+        assert!(!a.exists("out/b") && !b.exists("out/b"));
+    }
+
+    #[test]
+    fn tee_fail_fast() {
+        let t = Tee::new(MemFS::new(), MemFS::new());
+        t.get_ref().1.create_dir("dir").unwrap();
+        // the first side succeeds, then the second fails
+        assert_eq!(t.create_dir("dir").unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert!(t.get_ref().0.is_dir("dir"));
+    }
+}
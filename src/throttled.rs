@@ -0,0 +1,251 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Result, FileType, Metadata, OpenOptions, Permissions, QPath, Requalify};
+
+// a rate limit, tracked as units used since a start time
+struct Budget {
+    rate: u64,
+    used: u64,
+    start: Instant,
+}
+
+impl Budget {
+    fn new(rate: u64) -> Budget {
+        Budget { rate: rate, used: 0, start: Instant::now() }
+    }
+
+    // spend n units, returning how long to wait to stay under the rate
+    fn take(&mut self, n: u64) -> Duration {
+        let now = Instant::now();
+        let owed = Duration::from_secs_f64(self.used as f64 / self.rate as f64);
+        if now.duration_since(self.start) > owed {
+            // idle time doesn't build up credit for a later burst
+            self.start = now;
+            self.used = 0;
+        }
+        self.used += n;
+        let due = self.start + Duration::from_secs_f64(self.used as f64 / self.rate as f64);
+        due.saturating_duration_since(now)
+    }
+}
+
+struct Limits {
+    bytes: Option<Mutex<Budget>>,
+    ops: Option<Mutex<Budget>>,
+}
+
+fn spend(budget: &Option<Mutex<Budget>>, n: u64) {
+    if let Some(ref budget) = *budget {
+        let wait = budget.lock().unwrap().take(n);
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// A filesystem wrapper that limits how fast it can be used.
+///
+/// Every operation counts against an operations-per-second limit, and
+/// bytes read from opened files count against a bytes-per-second
+/// limit. Going over either limit sleeps the calling thread. This is
+/// handy for simulating slow or remote storage in tests, or for going
+/// easy on a shared disk.
+pub struct Throttled<T> {
+    inner: T,
+    limits: Arc<Limits>,
+}
+
+impl<T> Throttled<T> {
+    /// Wrap a filesystem with the given limits. `None` means unlimited,
+    /// and so does a limit of zero.
+    pub fn new(inner: T, bytes_per_sec: Option<u64>, ops_per_sec: Option<u64>) -> Throttled<T> {
+        let limits = Limits {
+            bytes: bytes_per_sec.filter(|&r| r > 0).map(|r| Mutex::new(Budget::new(r))),
+            ops: ops_per_sec.filter(|&r| r > 0).map(|r| Mutex::new(Budget::new(r))),
+        };
+        Throttled { inner: inner, limits: Arc::new(limits) }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    fn op(&self) {
+        spend(&self.limits.ops, 1);
+    }
+}
+
+/// A file opened through `Throttled`.
+pub struct ThrottledFile<R> {
+    inner: R,
+    limits: Arc<Limits>,
+}
+
+impl<R: io::Read> io::Read for ThrottledFile<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        spend(&self.limits.bytes, n as u64);
+        Ok(n)
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Throttled<T> {
+    type ReadFile = ThrottledFile<T::ReadFile>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<ThrottledFile<T::ReadFile>> {
+        self.op();
+        let f = self.inner.open(path)?;
+        Ok(ThrottledFile { inner: f, limits: self.limits.clone() })
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(ThrottledFile<T::ReadFile>, Metadata)> {
+        self.op();
+        let (f, meta) = self.inner.open_meta(path)?;
+        Ok((ThrottledFile { inner: f, limits: self.limits.clone() }, meta))
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.op();
+        self.inner.file_type(path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.op();
+        self.inner.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.op();
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.op();
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, Throttled<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.op();
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.op();
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        self.op();
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+impl<'a, T: FSWrite<'a>> FSWrite<'a> for Throttled<T> {
+    type WriteFile = T::WriteFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.op();
+        self.inner.create(path)
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<T::WriteFile> {
+        self.op();
+        self.inner.append(path)
+    }
+
+    fn open_options<P: AsRef<Path>>(&self, path: P, opts: OpenOptions) -> Result<T::WriteFile> {
+        self.op();
+        self.inner.open_options(path, opts)
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        self.op();
+        self.inner.truncate(path, len)
+    }
+
+    fn set_permissions<P: AsRef<Path>>(&self, path: P, perm: Permissions) -> Result<()> {
+        self.op();
+        self.inner.set_permissions(path, perm)
+    }
+
+    fn hard_link<P: AsRef<Path>, Q: AsRef<Path>>(&self, existing: P, link: Q) -> Result<()> {
+        self.op();
+        self.inner.hard_link(existing, link)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        self.op();
+        self.inner.rename(from, to)
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.op();
+        self.inner.remove_file(path)
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.op();
+        self.inner.remove_dir(path)
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.op();
+        self.inner.create_dir(path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS};
+    use std::io::{Read, Write};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn throttled_bytes() {
+        let m = MemFS::new();
+        m.create("a").unwrap().write_all(&[0; 1000]).unwrap();
+        let t = Throttled::new(m, Some(10000), None);
+
+        let start = Instant::now();
+        let mut v = Vec::new();
+        t.open("a").unwrap().read_to_end(&mut v).unwrap();
+        assert_eq!(v.len(), 1000);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn throttled_ops() {
+        let m = MemFS::new();
+        m.create("a").unwrap();
+        let t = Throttled::new(m, None, Some(100));
+
+        let start = Instant::now();
+        for _ in 0..5 {
+            assert!(t.is_file("a"));
+        }
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn throttled_zero() {
+        let m = MemFS::new();
+        m.create("a").unwrap().write_all(b"abc").unwrap();
+        let t = Throttled::new(m, Some(0), Some(0));
+        let mut v = Vec::new();
+        t.open("a").unwrap().read_to_end(&mut v).unwrap();
+        assert_eq!(v, b"abc");
+    }
+}
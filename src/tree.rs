@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use path::Path;
+use fs::FileType;
+
+/// A snapshot of a directory tree's layout, from `FSRead::tree`.
+///
+/// This records the name and type of everything under a directory,
+/// but no contents, so it can be browsed as often as needed without
+/// touching the filesystem again. Paths given to its methods are
+/// relative to the directory the snapshot was taken of. Changes made
+/// after the snapshot are not seen.
+#[derive(Clone, Debug)]
+pub struct Tree {
+    root: Node,
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    file_type: FileType,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn new(file_type: FileType) -> Node {
+        Node { file_type: file_type, children: BTreeMap::new() }
+    }
+}
+
+impl Tree {
+    pub(crate) fn new(file_type: FileType) -> Tree {
+        Tree { root: Node::new(file_type) }
+    }
+
+    // record an entry; its parent must be recorded already
+    pub(crate) fn insert<P: AsRef<Path>>(&mut self, path: P, file_type: FileType) {
+        let path = path.as_ref();
+        let parent = path.parent().and_then(|p| self.node_mut(p));
+        if let (Some(parent), Some(name)) = (parent, path.file_name()) {
+            parent.children.insert(name.to_owned(), Node::new(file_type));
+        }
+    }
+
+    fn node<P: AsRef<Path>>(&self, path: P) -> Option<&Node> {
+        path.as_ref().iter().try_fold(&self.root, |node, name| node.children.get(name))
+    }
+
+    fn node_mut<P: AsRef<Path>>(&mut self, path: P) -> Option<&mut Node> {
+        path.as_ref().iter().try_fold(&mut self.root, |node, name| node.children.get_mut(name))
+    }
+
+    /// The type of the entry at `path`, if there is one.
+    pub fn file_type<P: AsRef<Path>>(&self, path: P) -> Option<FileType> {
+        self.node(path).map(|n| n.file_type)
+    }
+
+    /// Whether there is an entry at `path`.
+    pub fn exists<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.node(path).is_some()
+    }
+
+    /// The names of the entries in the directory at `path`, in sorted
+    /// order, or `None` if there is no directory there.
+    pub fn children<P: AsRef<Path>>(&self, path: P) -> Option<Vec<&str>> {
+        match self.node(path) {
+            Some(n) if n.file_type.is_dir() => Some(n.children.keys().map(String::as_str).collect()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ::{FSRead, FSWrite, FileType, MemFS, Counting};
+
+    #[test]
+    fn tree_snapshot() {
+        let m = MemFS::new();
+        m.create_dir_all("site/tiles/0/1").unwrap();
+        m.create("site/tiles/0/1/2.png").unwrap();
+        m.create("site/index.html").unwrap();
+        m.create("elsewhere").unwrap();
+        let c = Counting::new(m);
+        let tree = c.tree("site").unwrap();
+
+        let (types, dirs) = (c.file_types(), c.read_dirs());
+        assert_eq!(tree.file_type("tiles/0/1/2.png"), Some(FileType::File));
+        assert_eq!(tree.file_type("/tiles/0/"), Some(FileType::Dir));
+        assert_eq!(tree.file_type(""), Some(FileType::Dir));
+        assert_eq!(tree.file_type("tiles/0/2"), None);
+        assert!(!tree.exists("elsewhere"));
+        assert_eq!(tree.children("").unwrap(), vec!["index.html", "tiles"]);
+        assert_eq!(tree.children("tiles/0/1").unwrap(), vec!["2.png"]);
+        assert!(tree.children("index.html").is_none());
+        assert!(tree.children("missing").is_none());
+        assert_eq!((c.file_types(), c.read_dirs()), (types, dirs));
+
+        c.get_ref().create("site/new").unwrap();
+        assert!(!tree.exists("new"));
+    }
+}
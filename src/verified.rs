@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::io;
+use sha2::{Digest, Sha256};
+use path::{Path, PathBuf};
+use fs::{FSRead, Result, FileType, Metadata, Permissions, QPath, Requalify};
+
+/// A filesystem wrapper that checks file contents against known hashes.
+///
+/// Files with an expected SHA-256 digest are hashed as they are read,
+/// and reaching the end of a file whose digest doesn't match is an
+/// `InvalidData` error. Files without an expected digest are read as
+/// usual. Note that nothing is checked unless the file is read all the
+/// way to the end.
+pub struct Verified<T> {
+    inner: T,
+    hashes: HashMap<PathBuf, [u8; 32]>,
+}
+
+impl<T> Verified<T> {
+    pub fn new(inner: T, hashes: HashMap<PathBuf, [u8; 32]>) -> Verified<T> {
+        Verified { inner: inner, hashes: hashes }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+/// A file opened through `Verified`.
+pub struct VerifiedFile<R> {
+    inner: R,
+    check: Option<(Sha256, [u8; 32])>,
+    failed: bool,
+}
+
+impl<R: io::Read> io::Read for VerifiedFile<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Checksum mismatch."));
+        }
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            if let Some((ref mut hasher, _)) = self.check {
+                hasher.update(&buf[..n]);
+            }
+        } else if !buf.is_empty() {
+            if let Some((hasher, expected)) = self.check.take() {
+                if hasher.finalize()[..] != expected[..] {
+                    self.failed = true;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Checksum mismatch."));
+                }
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl<'a, T: FSRead<'a>> FSRead<'a> for Verified<T> {
+    type ReadFile = VerifiedFile<T::ReadFile>;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<VerifiedFile<T::ReadFile>> {
+        let f = self.inner.open(&path)?;
+        let check = self.hashes.get(path.as_ref()).map(|h| (Sha256::new(), *h));
+        Ok(VerifiedFile { inner: f, check: check, failed: false })
+    }
+
+    fn open_meta<P: AsRef<Path>>(&self, path: P) -> Result<(VerifiedFile<T::ReadFile>, Metadata)> {
+        let (f, meta) = self.inner.open_meta(&path)?;
+        let check = self.hashes.get(path.as_ref()).map(|h| (Sha256::new(), *h));
+        Ok((VerifiedFile { inner: f, check: check, failed: false }, meta))
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        self.inner.file_type(path)
+    }
+
+    fn is_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.inner.is_symlink(path)
+    }
+
+    fn metadata<P: AsRef<Path>>(&self, path: P) -> Result<Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn permissions<P: AsRef<Path>>(&self, path: P) -> Result<Permissions> {
+        self.inner.permissions(path)
+    }
+
+    fn canonicalize<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
+        self.inner.canonicalize(path)
+    }
+
+    type ReadDir = Requalify<'a, T::ReadDir, Verified<T>>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<Self::ReadDir> {
+        self.inner.read_dir(path).map(|r| Requalify::new(r, self))
+    }
+
+    fn read_dir_all<P: AsRef<Path>>(&'a self, path: P) -> Result<Vec<QPath<'a, Self>>> {
+        self.inner.read_dir_all(path).map(|v| Requalify::new(v.into_iter(), self).collect())
+    }
+
+    fn read_dir_recursive_flat<P: AsRef<Path>>(&'a self, prefix: P)
+        -> Result<impl Iterator<Item=Result<QPath<'a, Self>>> + 'a>
+    {
+        let files = self.inner.read_dir_recursive_flat(prefix)?;
+        Ok(files.map(move |f| f.map(|p| self.qualified(p))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite, MemFS};
+    use std::io::{Read, Write};
+
+    fn digest(data: &[u8]) -> [u8; 32] {
+        let mut h = [0; 32];
+        h.copy_from_slice(&Sha256::digest(data));
+        h
+    }
+
+    #[test]
+    fn verified_read() {
+        let m = MemFS::new();
+        m.create("good").unwrap().write_all(b"asset").unwrap();
+        m.create("bad").unwrap().write_all(b"corrupted").unwrap();
+        m.create("other").unwrap().write_all(b"unchecked").unwrap();
+        let mut hashes = HashMap::new();
+        hashes.insert(PathBuf::from("good"), digest(b"asset"));
+        hashes.insert(PathBuf::from("bad"), digest(b"asset"));
+        let v = Verified::new(m, hashes);
+
+        let mut s = String::new();
+        v.open("/good").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "asset");
+
+        let err = v.open("bad").unwrap().read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut s = String::new();
+        v.open("other").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "unchecked");
+    }
+}
@@ -0,0 +1,83 @@
+use std::ops::Deref;
+use std::sync::mpsc::Receiver;
+use path::{Path, PathBuf};
+use fs::Result;
+
+/// A change to a watched file or directory.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WatchEvent {
+    Create(PathBuf),
+    Modify(PathBuf),
+    Remove(PathBuf),
+}
+
+impl WatchEvent {
+    /// The path that changed.
+    pub fn path(&self) -> &Path {
+        match *self {
+            WatchEvent::Create(ref p) | WatchEvent::Modify(ref p) | WatchEvent::Remove(ref p) => p,
+        }
+    }
+}
+
+/// The changes reported by a watch, from `Watch::watch`.
+///
+/// This derefs to the channel the events arrive on. Dropping it stops
+/// the watch.
+pub struct WatchEvents {
+    events: Receiver<WatchEvent>,
+    // whatever keeps the watch running, stopped when dropped
+    _guard: Box<dyn Send>,
+}
+
+impl WatchEvents {
+    /// Events arriving on `events` for as long as `guard` is kept.
+    pub fn new<G: Send + 'static>(events: Receiver<WatchEvent>, guard: G) -> WatchEvents {
+        WatchEvents { events: events, _guard: Box::new(guard) }
+    }
+}
+
+impl Deref for WatchEvents {
+    type Target = Receiver<WatchEvent>;
+
+    fn deref(&self) -> &Receiver<WatchEvent> {
+        &self.events
+    }
+}
+
+/// Filesystems that can report changes as they happen.
+pub trait Watch {
+    /// Watch a file, or a directory and everything under it.
+    ///
+    /// Events arrive until the returned `WatchEvents` is dropped.
+    /// Renames are reported as a removal and a creation.
+    fn watch<P: AsRef<Path>>(&self, path: P) -> Result<WatchEvents>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::mpsc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    struct Guard(Arc<AtomicBool>);
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn watch_events_drop() {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+        let events = WatchEvents::new(rx, Guard(stopped.clone()));
+        tx.send(WatchEvent::Create(PathBuf::from("a"))).unwrap();
+        assert_eq!(events.recv().unwrap().path(), "a");
+        assert!(!stopped.load(Ordering::SeqCst));
+        drop(events);
+        assert!(stopped.load(Ordering::SeqCst));
+    }
+}
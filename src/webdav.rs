@@ -0,0 +1,384 @@
+use std::{io, mem, vec};
+use reqwest::{Method, StatusCode, Url};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::CONTENT_TYPE;
+use path::{Path, PathBuf};
+use fs::{FSRead, FSWrite, Error, Result, FileType, QPath};
+
+const PROPFIND: &'static [u8] = b"PROPFIND";
+const PROPFIND_BODY: &'static str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+    <propfind xmlns=\"DAV:\"><prop><resourcetype/></prop></propfind>";
+
+/// A filesystem on a remote WebDAV server.
+///
+/// Paths map onto URL path segments under a base URL. Files are read
+/// with GET and listed with PROPFIND. Writes are buffered in memory
+/// and uploaded with PUT when the file is flushed or dropped, so flush
+/// explicitly to see any upload errors.
+pub struct WebDavFS {
+    base: Url,
+    client: Client,
+}
+
+fn error<P: AsRef<Path>>(op: &'static str, path: P, kind: io::ErrorKind, msg: &str) -> Error {
+    Error::new(op, path, io::Error::new(kind, msg))
+}
+
+// turn a failed request or an unsuccessful status into an error
+fn check<P: AsRef<Path>>(op: &'static str, path: P, resp: reqwest::Result<Response>) -> Result<Response> {
+    let resp = resp.map_err(|e| Error::new(op, &path, io::Error::new(io::ErrorKind::Other, e)))?;
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let kind = match status {
+        StatusCode::NOT_FOUND | StatusCode::GONE => io::ErrorKind::NotFound,
+        // WebDAV uses 409 when a parent collection is missing
+        StatusCode::CONFLICT => io::ErrorKind::NotFound,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => io::ErrorKind::PermissionDenied,
+        StatusCode::PRECONDITION_FAILED => io::ErrorKind::AlreadyExists,
+        StatusCode::METHOD_NOT_ALLOWED => io::ErrorKind::Unsupported,
+        _ => io::ErrorKind::Other,
+    };
+    Err(error(op, path, kind, status.canonical_reason().unwrap_or("Request failed.")))
+}
+
+// the text of every element with the given local name, ignoring namespace prefixes
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = rest[..end].split_whitespace().next().unwrap_or("");
+        let local = tag.rsplit(':').next().unwrap_or("").trim_end_matches('/');
+        if !tag.starts_with('/') && local == name {
+            let body = &rest[end + 1..];
+            found.push(if tag.ends_with('/') { "" } else { &body[..body.find('<').unwrap_or(body.len())] });
+        }
+        rest = &rest[end + 1..];
+    }
+    found
+}
+
+// undo percent-encoding in a URL path segment
+fn decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = segment.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+impl WebDavFS {
+    /// Connect to a WebDAV server, with all paths under `base`.
+    pub fn new(base: &str) -> Result<WebDavFS> {
+        WebDavFS::with_client(base, Client::new())
+    }
+
+    /// Like `new`, but use a preconfigured client, for example to set
+    /// timeouts or authentication headers.
+    pub fn with_client(base: &str, client: Client) -> Result<WebDavFS> {
+        let invalid = |msg: &str| Error::from(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        let mut base = Url::parse(base).map_err(|_| invalid("Invalid base URL."))?;
+        if base.cannot_be_a_base() {
+            return Err(invalid("Invalid base URL."));
+        }
+        base.path_segments_mut().unwrap().pop_if_empty();
+        Ok(WebDavFS { base: base, client: client })
+    }
+
+    fn url<P: AsRef<Path>>(&self, path: P, dir: bool) -> Url {
+        let mut url = self.base.clone();
+        {
+            let mut segments = url.path_segments_mut().unwrap();
+            segments.extend(path.as_ref());
+            if dir {
+                segments.push("");
+            }
+        }
+        url
+    }
+
+    // map a URL or absolute URL path from the server back into a path
+    fn unurl(&self, href: &str) -> Option<PathBuf> {
+        let url = self.base.join(&href.replace("&amp;", "&")).ok()?;
+        let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+        for base in self.base.path_segments()?.filter(|s| !s.is_empty()) {
+            if segments.next() != Some(base) {
+                return None;
+            }
+        }
+        let mut path = PathBuf::new();
+        for s in segments {
+            path.push(decode(s)?);
+        }
+        Some(path)
+    }
+
+    fn request(&self, method: Method, url: Url) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    fn propfind<P: AsRef<Path>>(&self, op: &'static str, path: P, depth: &str) -> Result<String> {
+        // only listings know they're asking about a collection
+        let req = self.request(Method::from_bytes(PROPFIND).unwrap(), self.url(&path, depth != "0"))
+            .header("Depth", depth)
+            .header(CONTENT_TYPE, "application/xml")
+            .body(PROPFIND_BODY);
+        let resp = check(op, &path, req.send())?;
+        resp.text().map_err(|e| Error::new(op, path, io::Error::new(io::ErrorKind::InvalidData, e)))
+    }
+
+    fn get<P: AsRef<Path>>(&self, op: &'static str, path: P) -> Result<Vec<u8>> {
+        let resp = check(op, &path, self.request(Method::GET, self.url(&path, false)).send())?;
+        let body = resp.bytes().map_err(|e| Error::new(op, &path, io::Error::new(io::ErrorKind::Other, e)))?;
+        Ok(body.to_vec())
+    }
+
+    fn put<P: AsRef<Path>>(&self, op: &'static str, path: P, data: Vec<u8>) -> Result<()> {
+        check(op, &path, self.request(Method::PUT, self.url(&path, false)).body(data).send()).map(|_| ())
+    }
+
+    fn writer<P: AsRef<Path>>(&self, path: P, data: Vec<u8>) -> WebDavFile {
+        WebDavFile {
+            client: self.client.clone(),
+            url: self.url(&path, false),
+            path: path.as_ref().to_path_buf(),
+            data: data,
+            dirty: false,
+        }
+    }
+}
+
+/// A writable file on a `WebDavFS`.
+///
+/// The contents are uploaded in full on every flush, and on drop if
+/// anything was written since the last flush.
+pub struct WebDavFile {
+    client: Client,
+    url: Url,
+    path: PathBuf,
+    data: Vec<u8>,
+    dirty: bool,
+}
+
+impl WebDavFile {
+    /// Upload any pending writes, and return the final contents.
+    pub fn finish(mut self) -> Result<Vec<u8>> {
+        io::Write::flush(&mut self)?;
+        Ok(mem::replace(&mut self.data, Vec::new()))
+    }
+}
+
+impl io::Write for WebDavFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.data.extend_from_slice(buf);
+        self.dirty = true;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.dirty {
+            let req = self.client.request(Method::PUT, self.url.clone()).body(self.data.clone());
+            check("create", &self.path, req.send())?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WebDavFile {
+    fn drop(&mut self) {
+        let _ = io::Write::flush(self);
+    }
+}
+
+pub struct ReadDir<'a> {
+    iter: vec::IntoIter<PathBuf>,
+    parent: &'a WebDavFS,
+}
+
+impl<'a> Iterator for ReadDir<'a> {
+    type Item = QPath<'a, WebDavFS>;
+
+    fn next(&mut self) -> Option<QPath<'a, WebDavFS>> {
+        self.iter.next().map(|p| self.parent.qualified(p))
+    }
+}
+
+impl<'a> FSRead<'a> for WebDavFS {
+    type ReadFile = Response;
+
+    fn open<P: AsRef<Path>>(&self, path: P) -> Result<Response> {
+        check("open", &path, self.request(Method::GET, self.url(&path, false)).send())
+    }
+
+    fn file_type<P: AsRef<Path>>(&self, path: P) -> Result<FileType> {
+        let xml = self.propfind("stat", path, "0")?;
+        if elements(&xml, "collection").is_empty() {
+            Ok(FileType::File)
+        } else {
+            Ok(FileType::Dir)
+        }
+    }
+
+    type ReadDir = ReadDir<'a>;
+
+    fn read_dir<P: AsRef<Path>>(&'a self, path: P) -> Result<ReadDir<'a>> {
+        let xml = self.propfind("read directory", &path, "1")?;
+        let depth = path.as_ref().component_count() + 1;
+        let children: Vec<PathBuf> = elements(&xml, "href").into_iter()
+            .filter_map(|href| self.unurl(href.trim()))
+            .filter(|p| p.component_count() == depth && p.parent() == Some(path.as_ref()))
+            .collect();
+        Ok(ReadDir { iter: children.into_iter(), parent: self })
+    }
+}
+
+impl<'a> FSWrite<'a> for WebDavFS {
+    type WriteFile = WebDavFile;
+
+    fn create<P: AsRef<Path>>(&self, path: P) -> Result<WebDavFile> {
+        self.put("create", &path, Vec::new())?;
+        Ok(self.writer(path, Vec::new()))
+    }
+
+    fn append<P: AsRef<Path>>(&self, path: P) -> Result<WebDavFile> {
+        let data = self.get("append to", &path)?;
+        Ok(self.writer(path, data))
+    }
+
+    fn truncate<P: AsRef<Path>>(&self, path: P, len: u64) -> Result<()> {
+        let mut data = self.get("truncate", &path)?;
+        data.resize(len as usize, 0);
+        self.put("truncate", path, data)
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(&self, from: P, to: Q) -> Result<()> {
+        let dir = self.is_dir(&from);
+        let req = self.request(Method::from_bytes(b"MOVE").unwrap(), self.url(&from, dir))
+            .header("Destination", self.url(&to, dir).as_str())
+            .header("Overwrite", "T");
+        check("rename", from, req.send()).map(|_| ())
+    }
+
+    fn remove_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if self.file_type(&path)?.is_dir() {
+            return Err(error("remove", path, io::ErrorKind::Other, "Is a directory."));
+        }
+        check("remove", &path, self.request(Method::DELETE, self.url(&path, false)).send()).map(|_| ())
+    }
+
+    fn remove_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        // DELETE on a collection is recursive, so check it's empty first
+        if self.read_dir(&path)?.next().is_some() {
+            return Err(error("remove", path, io::ErrorKind::Other, "Directory not empty."));
+        }
+        check("remove", &path, self.request(Method::DELETE, self.url(&path, true)).send()).map(|_| ())
+    }
+
+    fn create_dir<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let resp = self.request(Method::from_bytes(b"MKCOL").unwrap(), self.url(&path, true)).send();
+        match check("create directory", &path, resp) {
+            // MKCOL on an existing resource is not allowed
+            Err(ref e) if e.kind() == io::ErrorKind::Unsupported => {
+                Err(error("create directory", path, io::ErrorKind::AlreadyExists, "File exists."))
+            }
+            r => r.map(|_| ()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ::{FSRead, FSWrite};
+    use mockito::{Matcher, Server};
+    use std::io::{Read, Write};
+
+    const LISTING: &'static str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:multistatus xmlns:D="DAV:">
+  <D:response><D:href>/dav/dir/</D:href>
+    <D:propstat><D:prop><D:resourcetype><D:collection/></D:resourcetype></D:prop></D:propstat>
+  </D:response>
+  <D:response><D:href>/dav/dir/a%20b.txt</D:href>
+    <D:propstat><D:prop><D:resourcetype/></D:prop></D:propstat>
+  </D:response>
+  <D:response><D:href>http://example.com/dav/dir/sub/</D:href>
+    <D:propstat><D:prop><D:resourcetype><D:collection/></D:resourcetype></D:prop></D:propstat>
+  </D:response>
+</D:multistatus>"#;
+
+    #[test]
+    fn webdav() {
+        let mut server = Server::new();
+        let get = server.mock("GET", "/dav/dir/a%20b.txt").with_body("hello").create();
+        let missing = server.mock("GET", "/dav/missing").with_status(404).create();
+        let forbidden = server.mock("GET", "/dav/secret").with_status(403).create();
+        let list = server.mock("PROPFIND", "/dav/dir/")
+            .match_header("depth", "1")
+            .with_status(207)
+            .with_body(LISTING)
+            .create();
+        let stat = server.mock("PROPFIND", "/dav/dir")
+            .match_header("depth", "0")
+            .with_status(207)
+            .with_body(format!("{}</D:response></D:multistatus>", &LISTING[..LISTING.find("</D:response>").unwrap()]))
+            .create();
+        let put = server.mock("PUT", "/dav/new.txt")
+            .match_body(Matcher::Any)
+            .expect(2)
+            .create();
+        let file = server.mock("PROPFIND", "/dav/new.txt")
+            .with_status(207)
+            .with_body("<multistatus xmlns=\"DAV:\"><response><href>/dav/new.txt</href></response></multistatus>")
+            .create();
+        let delete = server.mock("DELETE", "/dav/new.txt").create();
+
+        let dav = WebDavFS::new(&format!("{}/dav/", server.url())).unwrap();
+        let mut s = String::new();
+        dav.open("dir/a b.txt").unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "hello");
+        assert_eq!(dav.open("missing").err().unwrap().kind(), io::ErrorKind::NotFound);
+        assert_eq!(dav.open("secret").err().unwrap().kind(), io::ErrorKind::PermissionDenied);
+
+        assert!(dav.is_dir("dir"));
+        let names: Vec<PathBuf> = dav.read_dir("dir").unwrap().map(|p| p.path().to_path_buf()).collect();
+        assert_eq!(names, vec!["dir/a b.txt", "dir/sub"]);
+
+        let mut f = dav.create("new.txt").unwrap();
+        f.write_all(b"data").unwrap();
+        assert_eq!(f.finish().unwrap(), b"data");
+        dav.remove_file("new.txt").unwrap();
+
+        get.assert();
+        missing.assert();
+        forbidden.assert();
+        list.assert();
+        stat.assert();
+        put.assert();
+        file.assert();
+        delete.assert();
+    }
+
+    #[test]
+    fn webdav_elements() {
+        assert_eq!(elements(LISTING, "href"), vec!["/dav/dir/", "/dav/dir/a%20b.txt", "http://example.com/dav/dir/sub/"]);
+        assert_eq!(elements(LISTING, "collection").len(), 2);
+        assert_eq!(decode("a%20b"), Some("a b".to_string()));
+        assert_eq!(decode("a%2"), None);
+    }
+}